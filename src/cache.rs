@@ -0,0 +1,96 @@
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const RENDERED_DIR: &str = "rendered";
+const CACHE_EXTENSION: &str = "json";
+
+/// Remembers what was rendered for each piece of markdown so unchanged posts
+/// don't have to be rendered and highlighted again.
+///
+/// Entries are keyed on the markdown itself along with the version of kblog
+/// and the other inputs to rendering, like the config and any custom syntaxes,
+/// so changing any of those invalidates everything.
+pub struct BuildCache {
+    dir: PathBuf,
+    salt: Vec<u8>,
+    used: Mutex<HashSet<PathBuf>>,
+    /// How many entries have been written, used to name their partial files.
+    partials: AtomicUsize,
+}
+
+impl BuildCache {
+    pub fn new<P: AsRef<Path>>(dir: P, inputs: &[u8]) -> BuildCache {
+        let mut salt = env!("CARGO_PKG_VERSION").as_bytes().to_vec();
+        salt.extend_from_slice(inputs);
+        BuildCache {
+            dir: dir.as_ref().join(RENDERED_DIR),
+            salt,
+            used: Mutex::new(HashSet::new()),
+            partials: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns what was cached for `markdown`, calling `render` and caching
+    /// its result if there is nothing.
+    pub fn rendered<T, F>(&self, markdown: &str, render: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T>,
+    {
+        let entry = self.entry_path(markdown);
+        self.used.lock().unwrap().insert(entry.clone());
+        let cached = fs::read(&entry)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok());
+        if let Some(rendered) = cached {
+            return Ok(rendered);
+        }
+
+        let rendered = render()?;
+        // Write then rename so an interrupted build can't leave a truncated
+        // entry behind. Posts with the same markdown share an entry, so each
+        // write needs a file of its own.
+        let partial = entry.with_extension(format!(
+            "{}.{}.partial",
+            std::process::id(),
+            self.partials.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&self.dir)?;
+        fs::write(&partial, serde_json::to_vec(&rendered)?)?;
+        fs::rename(&partial, &entry)?;
+        Ok(rendered)
+    }
+
+    /// Removes every entry that wasn't used during this build.
+    pub fn prune(&self) -> Result<()> {
+        if !self.dir.exists() {
+            return Ok(());
+        }
+
+        let used = self.used.lock().unwrap();
+        for entry in self.dir.read_dir()? {
+            let path = entry?.path();
+            if !used.contains(&path) {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, markdown: &str) -> PathBuf {
+        let hash = Sha256::new()
+            .chain_update(&self.salt)
+            .chain_update(markdown)
+            .finalize();
+        self.dir
+            .join(format!("{:x}", hash))
+            .with_extension(CACHE_EXTENSION)
+    }
+}
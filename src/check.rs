@@ -0,0 +1,161 @@
+use crate::document::{slugify, RawDraft, RawPost};
+use crate::markdown::{self, MarkdownError, MarkdownErrors, Reference};
+use crate::report::{ContentError, Report};
+use anyhow::anyhow;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+const POSTS_PATH: &str = "/posts/";
+const IMGS_PATH: &str = "/imgs/";
+
+/// Looks for problems that don't stop the site from being built but would
+/// leave it broken, such as links to posts that don't exist.
+pub struct Checker<'a> {
+    imgs_dir: &'a Path,
+    domain: &'a str,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(imgs_dir: &'a Path, domain: &'a str) -> Checker<'a> {
+        Checker { imgs_dir, domain }
+    }
+
+    pub fn check(&self, posts: &[RawPost], drafts: &[RawDraft], report: &mut Report) {
+        let post_ids: HashSet<&str> = posts.iter().map(|p| p.id.as_str()).collect();
+
+        for post in posts {
+            if let Some(updated) = post.updated.filter(|updated| *updated < post.date) {
+                let error = anyhow!(
+                    "updated date {} is before the publish date {}",
+                    updated.format("%Y-%m-%d"),
+                    post.date.format("%Y-%m-%d")
+                );
+                report.add(ContentError::new(&post.path, error));
+            }
+            self.check_references(
+                &post.path,
+                &post.markdown,
+                post.markdown_line,
+                &post_ids,
+                report,
+            );
+        }
+        for draft in drafts {
+            self.check_references(
+                &draft.path,
+                &draft.markdown,
+                draft.markdown_line,
+                &post_ids,
+                report,
+            );
+        }
+    }
+
+    fn check_references(
+        &self,
+        path: &Path,
+        markdown: &str,
+        markdown_line: usize,
+        post_ids: &HashSet<&str>,
+        report: &mut Report,
+    ) {
+        let errors: Vec<MarkdownError> = markdown::references(markdown)
+            .into_iter()
+            .filter_map(|reference| {
+                let problem = self.reference_problem(&reference, post_ids)?;
+                Some(MarkdownError::at_offset(
+                    markdown,
+                    reference.offset,
+                    problem,
+                ))
+            })
+            .collect();
+        if !errors.is_empty() {
+            let error = anyhow!(MarkdownErrors(errors));
+            report.add(ContentError::in_markdown(path, markdown_line, error));
+        }
+    }
+
+    fn reference_problem(
+        &self,
+        reference: &Reference,
+        post_ids: &HashSet<&str>,
+    ) -> Option<anyhow::Error> {
+        let url = self.site_path(&reference.url)?;
+        if reference.is_image {
+            let img = url.strip_prefix(IMGS_PATH)?;
+            (!self.imgs_dir.join(img).exists()).then(|| anyhow!("missing image {}", url))
+        } else {
+            let id = url.strip_prefix(POSTS_PATH)?.trim_end_matches('/');
+            (!post_ids.contains(id)).then(|| anyhow!("link to unknown post {}", url))
+        }
+    }
+
+    /// Returns the path portion of `url` if it points somewhere on this site.
+    fn site_path<'u>(&self, url: &'u str) -> Option<&'u str> {
+        let url = url.split(['#', '?']).next()?;
+        if url.starts_with('/') {
+            return Some(url);
+        }
+        ["https://", "http://"]
+            .iter()
+            .find_map(|scheme| url.strip_prefix(scheme)?.strip_prefix(self.domain))
+            .filter(|path| path.starts_with('/'))
+    }
+}
+
+/// Reports tags that are spelled differently but share a slug, like `C++`
+/// and `C#`, which would otherwise be merged into a single tag page.
+pub fn check_tag_slugs(posts: &[RawPost], report: &mut Report) {
+    let mut names_by_slug: HashMap<String, &str> = HashMap::new();
+    for post in posts {
+        for name in &post.front_matter.tags {
+            let slug = slugify(name);
+            let first = *names_by_slug.entry(slug.clone()).or_insert(name);
+            if !first.eq_ignore_ascii_case(name) {
+                let error = anyhow!(
+                    "tag \"{}\" has the same slug, {}, as the tag \"{}\"",
+                    name,
+                    slug,
+                    first
+                );
+                report.add(ContentError::new(&post.path, error));
+            }
+        }
+    }
+}
+
+/// Reports posts, and drafts, that would be written to the same place
+/// because they share an id.
+pub fn check_ids(posts: &[RawPost], drafts: &[RawDraft], report: &mut Report) {
+    check_duplicate_ids(
+        posts.iter().map(|p| (p.id.as_str(), p.path.as_path())),
+        report,
+    );
+    check_duplicate_ids(
+        drafts.iter().map(|d| (d.id.as_str(), d.path.as_path())),
+        report,
+    );
+}
+
+fn check_duplicate_ids<'p, I>(documents: I, report: &mut Report)
+where
+    I: Iterator<Item = (&'p str, &'p Path)>,
+{
+    let mut paths_by_id: HashMap<&str, Vec<&Path>> = HashMap::new();
+    for (id, path) in documents {
+        paths_by_id.entry(id).or_default().push(path);
+    }
+
+    for (id, paths) in paths_by_id.iter().filter(|(_, paths)| paths.len() > 1) {
+        for path in paths {
+            let others: Vec<String> = paths
+                .iter()
+                .filter(|other| *other != path)
+                .map(|other| other.display().to_string())
+                .collect();
+            let error = anyhow!("id \"{}\" is also used by {}", id, others.join(", "));
+            report.add(ContentError::new(path, error));
+        }
+    }
+}
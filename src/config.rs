@@ -1,12 +1,23 @@
 use anyhow::Result;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Deserialize)]
 pub struct Config {
     pub metadata: Metadata,
     pub themes: Themes,
+    #[serde(default)]
+    pub markdown: Markdown,
+    #[serde(default)]
+    pub index: Index,
+    #[serde(default)]
+    pub feed: Feed,
+    #[serde(default)]
+    pub robots: Robots,
+    #[serde(default)]
+    pub analytics: Analytics,
 }
 
 #[derive(Deserialize, Clone)]
@@ -18,7 +29,7 @@ pub struct Metadata {
     pub links: Vec<Link>,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Link {
     pub name: String,
     pub url: String,
@@ -36,6 +47,127 @@ pub struct Theme {
     pub text_color: String,
     pub link_color: String,
     pub footer_color: String,
+    /// Name of the theme used to highlight code.
+    pub code_theme: Option<String>,
+    #[serde(default)]
+    pub callouts: Callouts,
+}
+
+/// Colors of the `> [!NOTE]`, `> [!TIP]` and `> [!WARNING]` callouts.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Callouts {
+    pub note_color: String,
+    pub tip_color: String,
+    pub warning_color: String,
+}
+
+impl Default for Callouts {
+    fn default() -> Callouts {
+        Callouts {
+            note_color: String::from("#268bd2"),
+            tip_color: String::from("#859900"),
+            warning_color: String::from("#cb4b16"),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Markdown {
+    pub table_of_contents: bool,
+    pub heading_permalinks: bool,
+    /// Fail on code blocks in unknown languages, with unknown attributes or
+    /// with `hl_lines` past their end rather than warning about them.
+    pub strict_languages: bool,
+    /// Other names for code block languages, e.g. `zsh = "bash"`.
+    pub languages: BTreeMap<String, String>,
+    /// Show footnotes in the margin beside the text on wide screens, as well
+    /// as at the end of the post.
+    pub sidenotes: bool,
+    /// Render LaTeX between `$` signs as math.
+    pub math: bool,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Index {
+    /// Number of posts on each page of the index, or 0 for a single page.
+    pub posts_per_page: usize,
+    /// Show each post's summary under its title.
+    pub summaries: bool,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Feed {
+    /// Write an Atom feed to `atom.xml`.
+    pub atom: bool,
+    /// Write an RSS 2.0 feed to `rss.xml`.
+    pub rss: bool,
+    /// Write a JSON Feed to `feed.json`.
+    pub json: bool,
+    /// Include each post's full HTML in feeds instead of just its summary.
+    pub full_content: bool,
+    /// Number of posts in each feed, or 0 for every post.
+    pub max_entries: usize,
+}
+
+impl Default for Feed {
+    fn default() -> Feed {
+        Feed {
+            atom: true,
+            rss: false,
+            json: false,
+            full_content: false,
+            max_entries: 0,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Robots {
+    pub disallow: Vec<String>,
+}
+
+impl Default for Robots {
+    fn default() -> Robots {
+        Robots {
+            disallow: vec![String::from("/drafts/")],
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Analytics {
+    pub provider: AnalyticsProvider,
+    /// Google measurement id.
+    pub id: Option<String>,
+    /// GoatCounter site code.
+    pub code: Option<String>,
+    /// Plausible site domain, defaults to the blog's domain.
+    pub domain: Option<String>,
+    /// Plausible script URL, for self-hosted instances.
+    pub script: Option<String>,
+    /// File containing the HTML to include for a custom provider.
+    pub snippet: Option<PathBuf>,
+    /// Include analytics in pages built by `kblog serve`.
+    pub serve: bool,
+    /// Include analytics in drafts.
+    pub drafts: bool,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalyticsProvider {
+    #[default]
+    None,
+    Google,
+    Plausible,
+    GoatCounter,
+    Custom,
 }
 
 impl Config {
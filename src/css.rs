@@ -1,17 +1,21 @@
-use anyhow::Result;
+use anyhow::{anyhow, Error, Result};
 use askama::Template;
-use std::fs;
 use std::path::{Path, PathBuf};
-use syntect::highlighting::ThemeSet;
+use std::sync::Arc;
+use syntect::highlighting::{Theme, ThemeSet};
 use syntect::html::{css_for_theme_with_class_style, ClassStyle};
 
 use crate::config::Themes;
+use crate::output::Output;
 use crate::templates;
 
-pub const STYLE_FILE: &'static str = "style.css";
+pub const STYLE_FILE: &str = "style.css";
+const DEFAULT_LIGHT_CODE_THEME: &str = "Solarized (light)";
+const DEFAULT_DARK_CODE_THEME: &str = "Solarized (dark)";
 
 pub struct CSSCreator {
     out_dir: PathBuf,
+    output: Arc<Output>,
     main_themes: Themes,
     code_theme_set: ThemeSet,
 }
@@ -19,15 +23,20 @@ pub struct CSSCreator {
 impl CSSCreator {
     pub fn new<P: AsRef<Path>>(
         out_dir: P,
+        output: Arc<Output>,
         main_themes: Themes,
         code_theme_set: ThemeSet,
-    ) -> CSSCreator {
+    ) -> Result<CSSCreator> {
         let out_dir = out_dir.as_ref().to_path_buf();
-        CSSCreator {
+        let css_creator = CSSCreator {
             out_dir,
+            output,
             main_themes,
             code_theme_set,
-        }
+        };
+        css_creator.theme(css_creator.light_code_theme())?;
+        css_creator.theme(css_creator.dark_code_theme())?;
+        Ok(css_creator)
     }
 
     pub fn write_styles(&self) -> Result<()> {
@@ -46,23 +55,54 @@ impl CSSCreator {
     }
 
     fn write_light_code_style(&self) -> Result<()> {
-        let css = self.load_theme_css("Solarized (light)")?;
+        let css = self.load_theme_css(self.light_code_theme())?;
         self.write_css("code-theme-light.css", &css)
     }
 
     fn write_dark_code_style(&self) -> Result<()> {
-        let css = self.load_theme_css("Solarized (dark)")?;
+        let css = self.load_theme_css(self.dark_code_theme())?;
         self.write_css("code-theme-dark.css", &css)
     }
 
+    fn light_code_theme(&self) -> &str {
+        self.main_themes
+            .light
+            .code_theme
+            .as_deref()
+            .unwrap_or(DEFAULT_LIGHT_CODE_THEME)
+    }
+
+    fn dark_code_theme(&self) -> &str {
+        self.main_themes
+            .dark
+            .code_theme
+            .as_deref()
+            .unwrap_or(DEFAULT_DARK_CODE_THEME)
+    }
+
     fn load_theme_css(&self, name: &str) -> Result<String> {
-        let theme = &self.code_theme_set.themes[name];
-        let css = css_for_theme_with_class_style(theme, ClassStyle::Spaced)?;
+        let css = css_for_theme_with_class_style(self.theme(name)?, ClassStyle::Spaced)?;
         Ok(css)
     }
 
+    fn theme(&self, name: &str) -> Result<&Theme> {
+        self.code_theme_set
+            .themes
+            .get(name)
+            .ok_or_else(|| unknown_theme(name, &self.code_theme_set))
+    }
+
     fn write_css(&self, style_file: &str, css: &str) -> Result<()> {
         let css_file = self.out_dir.join(style_file);
-        Ok(fs::write(css_file, css)?)
+        self.output.write(css_file, css.as_bytes())
     }
 }
+
+fn unknown_theme(name: &str, theme_set: &ThemeSet) -> Error {
+    let available: Vec<&str> = theme_set.themes.keys().map(String::as_str).collect();
+    anyhow!(
+        "unknown code theme \"{}\", the available themes are: {}",
+        name,
+        available.join(", ")
+    )
+}
@@ -1,33 +1,135 @@
-use anyhow::{anyhow, Error, Result};
+use crate::markdown::TocEntry;
+use anyhow::{anyhow, Context, Error, Result};
 use chrono::prelude::*;
+use serde::{Deserialize, Serialize, Serializer};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+const FRONT_MATTER_DELIMITER: &str = "+++";
+
+/// Optional metadata declared in a `+++` delimited TOML block at the top of a
+/// post or draft.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub updated: Option<toml::value::Datetime>,
+    pub slug: Option<String>,
+    #[serde(default)]
+    pub draft: bool,
+    pub toc: Option<bool>,
+    pub series: Option<String>,
+}
+
+#[derive(Serialize)]
 pub struct RenderedPost {
     pub id: String,
     pub title: String,
     pub date: DateTime<FixedOffset>,
+    pub updated: Option<DateTime<FixedOffset>>,
+    pub tags: Vec<String>,
     pub summary: String,
     pub html: String,
+    pub toc: Option<Vec<TocEntry>>,
+    pub series: Option<String>,
+}
+
+/// What pages linking to a post get to know about it, leaving out its
+/// content so it isn't copied into every page that links to it.
+#[derive(Serialize)]
+pub struct PostLink<'p> {
+    pub id: &'p str,
+    pub title: &'p str,
+    pub date: DateTime<FixedOffset>,
+    pub updated: Option<DateTime<FixedOffset>>,
+    pub tags: &'p [String],
+    pub summary: &'p str,
+}
+
+impl<'p> From<&'p RenderedPost> for PostLink<'p> {
+    fn from(post: &'p RenderedPost) -> PostLink<'p> {
+        PostLink {
+            id: &post.id,
+            title: &post.title,
+            date: post.date,
+            updated: post.updated,
+            tags: &post.tags,
+            summary: &post.summary,
+        }
+    }
+}
+
+/// Serializes `posts` as links to them.
+fn serialize_links<S: Serializer>(
+    posts: &[&RenderedPost],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(posts.iter().map(|&post| PostLink::from(post)))
+}
+
+#[derive(Serialize)]
+pub struct Tag<'p> {
+    pub name: String,
+    pub slug: String,
+    #[serde(serialize_with = "serialize_links")]
+    pub posts: Vec<&'p RenderedPost>,
+}
+
+#[derive(Serialize)]
+pub struct Series<'p> {
+    pub name: String,
+    /// The posts in the series, oldest first.
+    #[serde(serialize_with = "serialize_links")]
+    pub posts: Vec<&'p RenderedPost>,
+}
+
+#[derive(Serialize)]
+pub struct ArchiveYear<'p> {
+    pub year: i32,
+    pub months: Vec<ArchiveMonth<'p>>,
+}
+
+#[derive(Serialize)]
+pub struct ArchiveMonth<'p> {
+    pub name: String,
+    #[serde(serialize_with = "serialize_links")]
+    pub posts: Vec<&'p RenderedPost>,
 }
 
 pub struct RawPost {
+    pub path: PathBuf,
     pub id: String,
     pub title: String,
     pub date: DateTime<FixedOffset>,
+    pub updated: Option<DateTime<FixedOffset>>,
     pub markdown: String,
+    pub markdown_line: usize,
+    pub front_matter: FrontMatter,
 }
 
 impl RawPost {
     pub fn new(path: &Path) -> Result<RawPost> {
         let (id, date) = id_and_date(path)?;
-        let (title, markdown) = title_and_markdown(path)?;
+        let (front_matter, title, markdown, markdown_line) = parse_content(path)?;
+        let updated = front_matter
+            .updated
+            .as_ref()
+            .map(|updated| parse_datetime(&updated.to_string()))
+            .transpose()
+            .context(bad_updated_date())?;
 
         Ok(RawPost {
-            id,
+            path: path.to_path_buf(),
+            id: front_matter.slug.clone().unwrap_or(id),
             title,
             date,
+            updated,
             markdown,
+            markdown_line,
+            front_matter,
         })
     }
 }
@@ -35,27 +137,63 @@ impl RawPost {
 pub struct RenderedDraft {
     pub id: String,
     pub title: String,
+    pub description: Option<String>,
     pub html: String,
+    pub toc: Option<Vec<TocEntry>>,
 }
 
 pub struct RawDraft {
+    pub path: PathBuf,
     pub id: String,
     pub title: String,
     pub markdown: String,
+    pub markdown_line: usize,
+    pub front_matter: FrontMatter,
+}
+
+/// The id a post at `path` with `front_matter` gets, whether or not the
+/// post exists yet.
+pub fn post_id(path: &Path, front_matter: &FrontMatter) -> Result<String> {
+    match &front_matter.slug {
+        Some(slug) => Ok(slug.clone()),
+        None => Ok(id_and_date(path)?.0),
+    }
+}
+
+/// Reads just the front matter of the post or draft at `path`.
+pub fn read_front_matter(path: &Path) -> Result<FrontMatter> {
+    let content = fs::read_to_string(path)?;
+    Ok(split_front_matter(&content, path)?.0)
 }
 
 impl RawDraft {
     pub fn new(path: &Path) -> Result<RawDraft> {
         let id = file_name(path)?.to_string();
-        let (title, markdown) = title_and_markdown(path)?;
+        let (front_matter, title, markdown, markdown_line) = parse_content(path)?;
         Ok(RawDraft {
-            id,
+            path: path.to_path_buf(),
+            id: front_matter.slug.clone().unwrap_or(id),
             title,
             markdown,
+            markdown_line,
+            front_matter,
         })
     }
 }
 
+impl From<RawPost> for RawDraft {
+    fn from(post: RawPost) -> RawDraft {
+        RawDraft {
+            path: post.path,
+            id: post.id,
+            title: post.title,
+            markdown: post.markdown,
+            markdown_line: post.markdown_line,
+            front_matter: post.front_matter,
+        }
+    }
+}
+
 fn id_and_date(path: &Path) -> Result<(String, DateTime<FixedOffset>)> {
     let file_name = file_name(path)?;
     let mut parts = file_name.split("_");
@@ -74,15 +212,85 @@ fn parse_date(date: &str) -> Result<DateTime<FixedOffset>> {
     Ok(parsed)
 }
 
-fn title_and_markdown(path: &Path) -> Result<(String, String)> {
+/// Parses a TOML date, local date and time or date and time with an offset.
+/// Dates and times without an offset are taken to be in UTC.
+fn parse_datetime(datetime: &str) -> Result<DateTime<FixedOffset>> {
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(datetime) {
+        return Ok(parsed);
+    }
+    if let Ok(parsed) = datetime.parse::<NaiveDateTime>() {
+        return Ok(parsed.and_utc().into());
+    }
+    parse_date(datetime).map_err(|_| unsupported_datetime(datetime))
+}
+
+/// Splits a file into its front matter, title and markdown, along with the
+/// line of the file on which the markdown starts.
+fn parse_content(path: &Path) -> Result<(FrontMatter, String, String, usize)> {
     let all_content = fs::read_to_string(path)?;
-    let mut parts = all_content.splitn(2, "\n\n");
+    let (front_matter, content) = split_front_matter(&all_content, path)?;
+    let (title, markdown) = match &front_matter.title {
+        Some(title) => (title.clone(), content.trim_start()),
+        None => title_and_markdown(content, path)?,
+    };
+    // The markdown is always the tail end of the file.
+    let header = &all_content[..all_content.len() - markdown.len()];
+    let markdown_line = header.matches('\n').count() + 1;
+    Ok((front_matter, title, markdown.to_string(), markdown_line))
+}
+
+fn split_front_matter<'c>(content: &'c str, path: &Path) -> Result<(FrontMatter, &'c str)> {
+    let Some(rest) = content.strip_prefix(FRONT_MATTER_DELIMITER) else {
+        return Ok((FrontMatter::default(), content));
+    };
+    let Some(rest) = rest.strip_prefix('\n').or(rest.strip_prefix("\r\n")) else {
+        return Ok((FrontMatter::default(), content));
+    };
+    let (raw_front_matter, markdown) =
+        split_at_closing_delimiter(rest).ok_or(unterminated_front_matter(path))?;
+    let front_matter: FrontMatter =
+        toml::from_str(raw_front_matter).with_context(|| bad_front_matter(path))?;
+    if let Some(tag) = front_matter.tags.iter().find(|tag| slugify(tag).is_empty()) {
+        return Err(empty_tag_slug(tag));
+    }
+    Ok((front_matter, markdown.trim_start_matches(['\r', '\n'])))
+}
+
+/// Splits `content` around the first line that's just the front matter
+/// delimiter.
+fn split_at_closing_delimiter(content: &str) -> Option<(&str, &str)> {
+    let mut line_start = 0;
+    for line in content.split_inclusive('\n') {
+        if line.trim_end_matches(['\r', '\n']) == FRONT_MATTER_DELIMITER {
+            return Some((&content[..line_start], &content[line_start + line.len()..]));
+        }
+        line_start += line.len();
+    }
+    None
+}
+
+fn title_and_markdown<'c>(content: &'c str, path: &Path) -> Result<(String, &'c str)> {
+    let mut parts = content.splitn(2, "\n\n");
     let title = parts.next().ok_or(missing_title(path))?;
     let markdown = parts.next().ok_or(missing_markdown(path))?;
-    Ok((title.to_string(), markdown.to_string()))
+    Ok((title.to_string(), markdown))
 }
 
-fn file_name<'a>(path: &'a Path) -> Result<&'a str> {
+/// Turns arbitrary text into a lowercase, hyphen separated string that is safe
+/// to use in a URL.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+fn file_name(path: &Path) -> Result<&str> {
     Ok(path
         .file_name()
         .ok_or(missing_file_name(path))?
@@ -114,3 +322,55 @@ fn missing_title(path: &Path) -> Error {
 fn missing_markdown(path: &Path) -> Error {
     anyhow!("post missing markdown {}", path.display())
 }
+
+fn unterminated_front_matter(path: &Path) -> Error {
+    anyhow!("front matter missing closing +++ {}", path.display())
+}
+
+fn empty_tag_slug(tag: &str) -> Error {
+    anyhow!("tag \"{}\" needs a letter or digit to make a URL from", tag)
+}
+
+fn bad_front_matter(path: &Path) -> String {
+    format!("couldn't parse front matter of {}", path.display())
+}
+
+fn bad_updated_date() -> &'static str {
+    "bad updated date in front matter"
+}
+
+fn unsupported_datetime(datetime: &str) -> Error {
+    anyhow!(
+        "{} isn't a date or a date and time, expected e.g. 2024-03-01, 2024-03-01T10:00:00 or 2024-03-01T10:00:00Z",
+        datetime
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(content: &str) -> Result<(FrontMatter, &str)> {
+        split_front_matter(content, Path::new("post.md"))
+    }
+
+    #[test]
+    fn front_matter_ends_at_its_own_line() {
+        let (front_matter, markdown) =
+            split("+++\ntitle = \"\"\"\n+++foo\"\"\"\n+++\n++++\nText\n").unwrap();
+        assert_eq!(front_matter.title.as_deref(), Some("+++foo"));
+        assert_eq!(markdown, "++++\nText\n");
+    }
+
+    #[test]
+    fn front_matter_can_end_the_file() {
+        let (front_matter, markdown) = split("+++\r\ntitle = \"T\"\r\n+++").unwrap();
+        assert_eq!(front_matter.title.as_deref(), Some("T"));
+        assert_eq!(markdown, "");
+    }
+
+    #[test]
+    fn unterminated_front_matter_fails() {
+        assert!(split("+++\ntitle = \"T\"\n++++\nText\n").is_err());
+    }
+}
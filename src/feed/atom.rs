@@ -0,0 +1,89 @@
+use super::{FeedEntry, SiteFeed, ATOM};
+use crate::document;
+use anyhow::Result;
+use atom_syndication::{
+    Category, CategoryBuilder, Content, ContentBuilder, Entry, EntryBuilder, FeedBuilder, Link,
+    LinkBuilder, Person, PersonBuilder, Text,
+};
+
+pub fn write(feed: &SiteFeed) -> Result<Vec<u8>> {
+    let atom = FeedBuilder::default()
+        .id(feed.id.clone())
+        .title(plain_text(&feed.title))
+        .subtitle(plain_text(&feed.subtitle))
+        .updated(feed.updated)
+        .links(feed_links(feed))
+        .author(author(feed))
+        .rights(plain_text(&feed.rights))
+        .entries(
+            feed.entries
+                .iter()
+                .map(|e| create_entry(feed, e))
+                .collect::<Vec<_>>(),
+        )
+        .build();
+    Ok(atom.write_to(Vec::new())?)
+}
+
+fn feed_links(feed: &SiteFeed) -> Vec<Link> {
+    let self_link = LinkBuilder::default()
+        .href(feed.feed_url(&ATOM))
+        .rel("self")
+        .mime_type(ATOM.mime_type.to_string())
+        .build();
+    let alt_link = html_link(&feed.home_url);
+    vec![self_link, alt_link]
+}
+
+fn create_entry(feed: &SiteFeed, entry: &FeedEntry) -> Entry {
+    EntryBuilder::default()
+        .id(entry.id.clone())
+        .title(plain_text(&entry.title))
+        .summary(plain_text(&entry.summary))
+        .content(entry.content.as_deref().map(html_content))
+        .categories(categories(entry))
+        .published(entry.published)
+        .updated(entry.updated)
+        .link(html_link(&entry.url))
+        .author(author(feed))
+        .build()
+}
+
+fn html_content(html: &str) -> Content {
+    ContentBuilder::default()
+        .value(html.to_string())
+        .content_type("html".to_string())
+        .build()
+}
+
+fn categories(entry: &FeedEntry) -> Vec<Category> {
+    entry
+        .tags
+        .iter()
+        .map(|tag| {
+            CategoryBuilder::default()
+                .term(document::slugify(tag))
+                .label(tag.clone())
+                .build()
+        })
+        .collect()
+}
+
+fn html_link(url: &str) -> Link {
+    LinkBuilder::default()
+        .href(url)
+        .rel("alternate")
+        .mime_type("text/html".to_string())
+        .build()
+}
+
+fn author(feed: &SiteFeed) -> Person {
+    PersonBuilder::default()
+        .name(&feed.author)
+        .uri(feed.author_url.clone())
+        .build()
+}
+
+fn plain_text(text: &str) -> Text {
+    Text::plain(text)
+}
@@ -0,0 +1,71 @@
+use super::{FeedEntry, SiteFeed, JSON};
+use anyhow::Result;
+use serde::Serialize;
+
+const VERSION: &str = "https://jsonfeed.org/version/1.1";
+
+/// A feed in the [JSON Feed](https://jsonfeed.org/version/1.1) format.
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    description: &'a str,
+    home_page_url: &'a str,
+    feed_url: &'a str,
+    authors: [Author<'a>; 1],
+    items: Vec<JsonItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct Author<'a> {
+    name: &'a str,
+    url: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonItem<'a> {
+    id: &'a str,
+    url: &'a str,
+    title: &'a str,
+    summary: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_html: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_text: Option<&'a str>,
+    date_published: String,
+    date_modified: String,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tags: &'a [String],
+}
+
+pub fn write(feed: &SiteFeed) -> Result<Vec<u8>> {
+    let json = JsonFeed {
+        version: VERSION,
+        title: &feed.title,
+        description: &feed.subtitle,
+        home_page_url: &feed.home_url,
+        feed_url: feed.feed_url(&JSON),
+        authors: [Author {
+            name: &feed.author,
+            url: &feed.author_url,
+        }],
+        items: feed.entries.iter().map(create_item).collect(),
+    };
+    Ok(serde_json::to_vec_pretty(&json)?)
+}
+
+/// Every item needs some content, so fall back to the summary when the full
+/// post isn't included.
+fn create_item(entry: &FeedEntry) -> JsonItem<'_> {
+    JsonItem {
+        id: &entry.id,
+        url: &entry.url,
+        title: &entry.title,
+        summary: &entry.summary,
+        content_html: entry.content.as_deref(),
+        content_text: entry.content.is_none().then_some(entry.summary.as_str()),
+        date_published: entry.published.to_rfc3339(),
+        date_modified: entry.updated.to_rfc3339(),
+        tags: &entry.tags,
+    }
+}
@@ -0,0 +1,248 @@
+mod atom;
+mod json;
+mod rss;
+
+use crate::config::{self, Metadata};
+use crate::document::{RenderedPost, Tag};
+use crate::output::Output;
+use anyhow::Result;
+use atom_syndication::FixedDateTime;
+use chrono::Datelike;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A format feeds can be written in.
+#[derive(Serialize)]
+pub struct Format {
+    pub name: &'static str,
+    pub file: &'static str,
+    pub mime_type: &'static str,
+    #[serde(skip)]
+    write: fn(&SiteFeed) -> Result<Vec<u8>>,
+}
+
+pub const ATOM: Format = Format {
+    name: "Atom",
+    file: "atom.xml",
+    mime_type: "application/atom+xml",
+    write: atom::write,
+};
+
+pub const RSS: Format = Format {
+    name: "RSS",
+    file: "rss.xml",
+    mime_type: "application/rss+xml",
+    write: rss::write,
+};
+
+pub const JSON: Format = Format {
+    name: "JSON",
+    file: "feed.json",
+    mime_type: "application/feed+json",
+    write: json::write,
+};
+
+/// A feed of posts, independent of the format it ends up written in.
+pub struct SiteFeed {
+    pub id: String,
+    pub title: String,
+    pub subtitle: String,
+    pub home_url: String,
+    /// The URL of each format of the feed, keyed by file name.
+    pub feed_urls: Vec<(&'static str, String)>,
+    pub updated: FixedDateTime,
+    pub author: String,
+    pub author_url: String,
+    pub rights: String,
+    pub entries: Vec<FeedEntry>,
+}
+
+impl SiteFeed {
+    fn feed_url(&self, format: &Format) -> &str {
+        self.feed_urls
+            .iter()
+            .find(|(file, _)| *file == format.file)
+            .map(|(_, url)| url.as_str())
+            .unwrap_or(&self.home_url)
+    }
+}
+
+pub struct FeedEntry {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub summary: String,
+    /// The full HTML of the post, when feeds include it.
+    pub content: Option<String>,
+    pub published: FixedDateTime,
+    pub updated: FixedDateTime,
+    pub tags: Vec<String>,
+}
+
+pub struct FeedCreator {
+    out_dir: PathBuf,
+    output: Arc<Output>,
+    now: FixedDateTime,
+    metadata: Metadata,
+    config: config::Feed,
+    formats: Vec<&'static Format>,
+}
+
+impl FeedCreator {
+    pub fn new<P, D>(
+        out_dir: P,
+        output: Arc<Output>,
+        now: D,
+        metadata: Metadata,
+        config: config::Feed,
+    ) -> FeedCreator
+    where
+        P: AsRef<Path>,
+        D: Into<FixedDateTime>,
+    {
+        let out_dir = out_dir.as_ref().to_path_buf();
+        let now = now.into();
+        let formats = [
+            (config.atom, &ATOM),
+            (config.rss, &RSS),
+            (config.json, &JSON),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, format)| enabled.then_some(format))
+        .collect();
+        FeedCreator {
+            out_dir,
+            output,
+            now,
+            metadata,
+            config,
+            formats,
+        }
+    }
+
+    /// The formats every feed is written in.
+    pub fn formats(&self) -> &[&'static Format] {
+        &self.formats
+    }
+
+    pub fn render_feed(&self, posts: &[RenderedPost]) -> Result<()> {
+        let feed = self.create_feed(
+            format!("tag:{}", self.metadata.domain),
+            self.metadata.blog_name.clone(),
+            "",
+            posts.iter(),
+        );
+        self.write_feed("", &feed)
+    }
+
+    pub fn render_tag_feed(&self, tag: &Tag) -> Result<()> {
+        let dir = format!("tags/{}/", tag.slug);
+        let feed = self.create_feed(
+            format!("tag:{}:tags:{}", self.metadata.domain, tag.slug),
+            format!("{}: {}", self.metadata.blog_name, tag.name),
+            &dir,
+            tag.posts.iter().copied(),
+        );
+        self.write_feed(&dir, &feed)
+    }
+
+    fn create_feed<'p, I>(&self, id: String, title: String, dir: &str, posts: I) -> SiteFeed
+    where
+        I: Iterator<Item = &'p RenderedPost>,
+    {
+        let entries = self.create_entries(posts);
+        let updated = entries.iter().map(|e| e.updated).max().unwrap_or(self.now);
+        let feed_urls = self
+            .formats
+            .iter()
+            .map(|format| (format.file, self.url(&format!("{}{}", dir, format.file))))
+            .collect();
+
+        SiteFeed {
+            id,
+            title,
+            subtitle: self.metadata.blog_subtitle.clone(),
+            home_url: self.url(dir),
+            feed_urls,
+            updated,
+            author: self.metadata.author.clone(),
+            author_url: self.url(""),
+            rights: format!("© {} {}", self.now.year(), self.metadata.author),
+            entries,
+        }
+    }
+
+    fn write_feed(&self, dir: &str, feed: &SiteFeed) -> Result<()> {
+        for format in &self.formats {
+            let contents = (format.write)(feed)?;
+            self.output
+                .write(self.out_dir.join(dir).join(format.file), &contents)?;
+        }
+        Ok(())
+    }
+
+    fn create_entries<'p, I>(&self, posts: I) -> Vec<FeedEntry>
+    where
+        I: Iterator<Item = &'p RenderedPost>,
+    {
+        let max_entries = match self.config.max_entries {
+            0 => usize::MAX,
+            max => max,
+        };
+        posts
+            .take(max_entries)
+            .map(|p| self.create_entry(p))
+            .collect()
+    }
+
+    fn create_entry(&self, post: &RenderedPost) -> FeedEntry {
+        let content = self
+            .config
+            .full_content
+            .then(|| absolute_urls(&post.html, &self.url("")));
+        FeedEntry {
+            id: self.entry_id(post),
+            title: post.title.clone(),
+            url: self.url(&format!("posts/{}", post.id)),
+            summary: post.summary.clone(),
+            content,
+            published: post.date,
+            updated: post.updated.unwrap_or(post.date),
+            tags: post.tags.clone(),
+        }
+    }
+
+    fn entry_id(&self, post: &RenderedPost) -> String {
+        format!(
+            "tag:{},{}:{}",
+            self.metadata.domain,
+            post.date.format("%Y-%m-%d"),
+            post.id
+        )
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("https://{}/{}", self.metadata.domain, path)
+    }
+}
+
+/// Prefixes root-relative `src` and `href` attributes, like `/imgs/a.png`,
+/// with the site's URL so they still work when the HTML is shown by a feed
+/// reader.
+fn absolute_urls(html: &str, site_url: &str) -> String {
+    let site_url = site_url.trim_end_matches('/');
+    let mut absolute = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(i) = rest.find("=\"/") {
+        let (before, after) = rest.split_at(i + 2);
+        absolute.push_str(before);
+        let is_url_attribute = before.ends_with("src=\"") || before.ends_with("href=\"");
+        if is_url_attribute && !after.starts_with("//") {
+            absolute.push_str(site_url);
+        }
+        rest = after;
+    }
+    absolute.push_str(rest);
+    absolute
+}
@@ -0,0 +1,49 @@
+use super::{FeedEntry, SiteFeed, RSS};
+use anyhow::Result;
+use rss::extension::atom::{AtomExtension, Link};
+use rss::{Category, Channel, Guid, Item};
+
+pub fn write(feed: &SiteFeed) -> Result<Vec<u8>> {
+    let self_link = Link {
+        href: feed.feed_url(&RSS).to_string(),
+        rel: String::from("self"),
+        mime_type: Some(RSS.mime_type.to_string()),
+        ..Default::default()
+    };
+    let channel = Channel {
+        title: feed.title.clone(),
+        link: feed.home_url.clone(),
+        description: feed.subtitle.clone(),
+        copyright: Some(feed.rights.clone()),
+        last_build_date: Some(feed.updated.to_rfc2822()),
+        items: feed.entries.iter().map(create_item).collect(),
+        atom_ext: Some(AtomExtension {
+            links: vec![self_link],
+        }),
+        ..Default::default()
+    };
+    Ok(channel.write_to(Vec::new())?)
+}
+
+fn create_item(entry: &FeedEntry) -> Item {
+    Item {
+        title: Some(entry.title.clone()),
+        link: Some(entry.url.clone()),
+        description: Some(entry.summary.clone()),
+        content: entry.content.clone(),
+        categories: entry
+            .tags
+            .iter()
+            .map(|tag| Category {
+                name: tag.clone(),
+                domain: None,
+            })
+            .collect(),
+        guid: Some(Guid {
+            value: entry.id.clone(),
+            permalink: false,
+        }),
+        pub_date: Some(entry.published.to_rfc2822()),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use syntect::dumps;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+
+const HIGHLIGHTING_DIR: &str = "highlighting";
+const DUMP_EXTENSION: &str = "packdump";
+
+/// The syntaxes and themes used to highlight code, made up of syntect's
+/// defaults along with any the site provides.
+pub struct Highlighting {
+    pub syntax_set: SyntaxSet,
+    pub theme_set: ThemeSet,
+    /// Changes whenever the site's syntaxes or themes change.
+    pub fingerprint: String,
+}
+
+impl Highlighting {
+    /// Loads the `.sublime-syntax` files in `syntaxes_dir` and the `.tmTheme`
+    /// files in `themes_dir` on top of the defaults.
+    ///
+    /// Building syntaxes is slow, so the merged sets are dumped into
+    /// `cache_dir` and loaded from there until the site's files change.
+    pub fn load<P: AsRef<Path>>(
+        syntaxes_dir: P,
+        themes_dir: P,
+        cache_dir: P,
+    ) -> Result<Highlighting> {
+        let syntaxes_dir = syntaxes_dir.as_ref();
+        let themes_dir = themes_dir.as_ref();
+        let mut files = site_files(syntaxes_dir)?;
+        files.extend(site_files(themes_dir)?);
+        if files.is_empty() {
+            return Ok(Highlighting {
+                syntax_set: SyntaxSet::load_defaults_newlines(),
+                theme_set: ThemeSet::load_defaults(),
+                fingerprint: String::new(),
+            });
+        }
+
+        let fingerprint = fingerprint(&files)?;
+        let dump_dir = cache_dir.as_ref().join(HIGHLIGHTING_DIR);
+        let dump = dump_dir.join(&fingerprint).with_extension(DUMP_EXTENSION);
+        let (syntax_set, theme_set) = match dumps::from_dump_file(&dump) {
+            Ok(sets) => sets,
+            Err(_) => {
+                let sets = build_sets(syntaxes_dir, themes_dir)?;
+                // Only the dump for the current files is worth keeping.
+                let _ = fs::remove_dir_all(&dump_dir);
+                fs::create_dir_all(&dump_dir)?;
+                dumps::dump_to_file(&sets, &dump)?;
+                sets
+            }
+        };
+        Ok(Highlighting {
+            syntax_set,
+            theme_set,
+            fingerprint,
+        })
+    }
+}
+
+fn build_sets(syntaxes_dir: &Path, themes_dir: &Path) -> Result<(SyntaxSet, ThemeSet)> {
+    let mut syntaxes = SyntaxSet::load_defaults_newlines().into_builder();
+    if syntaxes_dir.exists() {
+        syntaxes
+            .add_from_folder(syntaxes_dir, true)
+            .with_context(|| format!("failed to load syntaxes from {}", syntaxes_dir.display()))?;
+    }
+    let mut theme_set = ThemeSet::load_defaults();
+    if themes_dir.exists() {
+        theme_set
+            .add_from_folder(themes_dir)
+            .with_context(|| format!("failed to load themes from {}", themes_dir.display()))?;
+    }
+    Ok((syntaxes.build(), theme_set))
+}
+
+/// Finds every file under `dir`, in a stable order.
+fn site_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+    for entry in dir.read_dir()? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(site_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn fingerprint(files: &[PathBuf]) -> Result<String> {
+    let mut hasher = Sha256::new().chain_update(env!("CARGO_PKG_VERSION"));
+    for file in files {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update(fs::read(file)?);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -1,67 +1,271 @@
+mod cache;
+mod check;
 mod config;
 mod css;
 mod document;
 mod feed;
+mod highlighting;
 mod markdown;
+mod math;
+mod output;
+mod publish;
 mod render;
+mod report;
+mod scaffold;
+mod serve;
 mod templates;
 
+use crate::cache::BuildCache;
 use crate::css::CSSCreator;
 use crate::feed::FeedCreator;
+use crate::highlighting::Highlighting;
 use crate::markdown::Markdowner;
-use crate::render::Renderer;
+use crate::output::Output;
+use crate::render::{Mode, Renderer};
+use crate::serve::Server;
+use crate::templates::{Analytics, Templates};
 use anyhow::Result;
-use chrono::{Datelike, Utc};
-use clap::Parser;
+use chrono::{Datelike, Local, NaiveDate, Utc};
+use clap::{Parser, Subcommand};
 use config::Config;
 use std::env;
+use std::fs;
 use std::path::PathBuf;
-use syntect::highlighting::ThemeSet;
-use syntect::parsing::SyntaxSet;
+use std::process;
+use std::sync::Arc;
 
-const DEFAULT_IN_DIR: &'static str = "content";
-const DEFAULT_OUT_DIR: &'static str = "gen";
-const CONFIG_FILE_NAME: &'static str = "config.toml";
+const DEFAULT_IN_DIR: &str = "content";
+const DEFAULT_OUT_DIR: &str = "gen";
+const CONFIG_FILE_NAME: &str = "config.toml";
+const TEMPLATES_DIR: &str = "templates";
+const CACHE_DIR: &str = ".kblog-cache";
+const SYNTAXES_DIR: &str = "syntaxes";
+const THEMES_DIR: &str = "themes";
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 struct Args {
     /// Optional directory from which the program should run.
-    #[arg(short = 'C', value_name = "DIR")]
+    #[arg(short = 'C', value_name = "DIR", global = true)]
     working_directory: Option<PathBuf>,
+
+    /// Number of threads to render with. Defaults to the number of CPUs.
+    #[arg(short, long, value_name = "N", global = true)]
+    jobs: Option<usize>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Build the site, serve it locally and rebuild it whenever it changes.
+    Serve {
+        /// Address on which to listen.
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+
+        /// Port on which to listen.
+        #[arg(short, long, default_value_t = 1111)]
+        port: u16,
+    },
+    /// Render everything and look for problems in the content without
+    /// writing the site.
+    Check,
+    /// Move a draft into the posts directory, publishing it.
+    Publish {
+        /// Name of the draft to publish, e.g. my-great-draft.
+        draft: String,
+
+        /// Publish date in YYYY-MM-DD format. Defaults to today.
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+    /// List the themes available for highlighting code.
+    Themes,
+    /// Create a new draft, post or site.
+    New {
+        #[command(subcommand)]
+        kind: NewCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum NewCommand {
+    /// Create an empty draft.
+    Draft {
+        /// Title of the draft, which is also used to make its slug.
+        title: String,
+    },
+    /// Create an empty post.
+    Post {
+        /// Title of the post, which is also used to make its slug.
+        title: String,
+
+        /// Publish date in YYYY-MM-DD format. Defaults to today.
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+    /// Create the directory layout and config for a new site.
+    Site {
+        /// Directory in which to create the site.
+        dir: PathBuf,
+    },
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("{:#}", e);
+        process::exit(1);
+    }
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+fn run(args: Args) -> Result<()> {
     if let Some(dir) = args.working_directory {
         env::set_current_dir(dir)?
     };
 
+    let jobs = args.jobs.unwrap_or(0);
+    match args.command {
+        None => build(Mode::Build, jobs),
+        Some(Command::Check) => check(jobs),
+        Some(Command::Serve { bind, port }) => serve(&bind, port, jobs),
+        Some(Command::Publish { draft, date }) => publish(&draft, date),
+        Some(Command::New { kind }) => new(kind),
+        Some(Command::Themes) => themes(),
+    }
+}
+
+fn serve(bind: &str, port: u16, jobs: usize) -> Result<()> {
+    // A broken post shouldn't stop the server from coming up, the next save
+    // will trigger another build.
+    if let Err(e) = build(Mode::Serve, jobs) {
+        eprintln!("{:#}", e);
+    }
+    let server = Server::new(DEFAULT_OUT_DIR, &format!("{}:{}", bind, port))?;
+    println!("Serving site at http://{}:{}/", bind, port);
+    server.run(
+        &[
+            DEFAULT_IN_DIR,
+            CONFIG_FILE_NAME,
+            TEMPLATES_DIR,
+            SYNTAXES_DIR,
+            THEMES_DIR,
+        ],
+        move || build(Mode::Serve, jobs),
+    )
+}
+
+fn check(jobs: usize) -> Result<()> {
+    build(Mode::Check, jobs)?;
+    println!("no problems found");
+    Ok(())
+}
+
+fn publish(draft: &str, date: Option<NaiveDate>) -> Result<()> {
+    let config = Config::from_toml(CONFIG_FILE_NAME)?;
+    let date = date.unwrap_or_else(|| Local::now().date_naive());
+    let published = publish::publish(DEFAULT_IN_DIR, draft, date)?;
+    println!("Published {}", published.path.display());
+    println!(
+        "It will be at https://{}/posts/{}",
+        config.metadata.domain, published.id
+    );
+    Ok(())
+}
+
+fn new(kind: NewCommand) -> Result<()> {
+    match kind {
+        NewCommand::Draft { title } => {
+            let path = scaffold::new_draft(DEFAULT_IN_DIR, &title)?;
+            println!("Created {}", path.display());
+        }
+        NewCommand::Post { title, date } => {
+            let date = date.unwrap_or_else(|| Local::now().date_naive());
+            let path = scaffold::new_post(DEFAULT_IN_DIR, &title, date)?;
+            println!("Created {}", path.display());
+        }
+        NewCommand::Site { dir } => {
+            scaffold::new_site(&dir, CONFIG_FILE_NAME, DEFAULT_IN_DIR)?;
+            println!(
+                "Created a new site in {}, fill in {} to get started",
+                dir.display(),
+                CONFIG_FILE_NAME
+            );
+        }
+    }
+    Ok(())
+}
+
+fn themes() -> Result<()> {
+    let highlighting = Highlighting::load(SYNTAXES_DIR, THEMES_DIR, CACHE_DIR)?;
+    for name in highlighting.theme_set.themes.keys() {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+fn build(mode: Mode, jobs: usize) -> Result<()> {
     let now = Utc::now();
     let year = format!("{}", now.year());
-    let syntax_set = SyntaxSet::load_defaults_newlines();
-    let md = Markdowner::new(syntax_set);
-    let theme_set = ThemeSet::load_defaults();
     let config = Config::from_toml(CONFIG_FILE_NAME)?;
-    let css_creator = CSSCreator::new(DEFAULT_OUT_DIR, config.themes.clone(), theme_set);
-    let feed_creator = FeedCreator::new(DEFAULT_OUT_DIR, now, config.metadata.clone());
+    let highlighting = Highlighting::load(SYNTAXES_DIR, THEMES_DIR, CACHE_DIR)?;
+    let md = Markdowner::new(highlighting.syntax_set, config.markdown.clone());
+    let mut inputs = fs::read(CONFIG_FILE_NAME)?;
+    inputs.extend_from_slice(highlighting.fingerprint.as_bytes());
+    let cache = BuildCache::new(CACHE_DIR, &inputs);
+    let templates = Templates::new(TEMPLATES_DIR)?;
+    let analytics = Analytics::from_config(&config.analytics, &config.metadata.domain)?;
+    let output = Arc::new(Output::new(DEFAULT_OUT_DIR, mode == Mode::Check));
+    let css_creator = CSSCreator::new(
+        DEFAULT_OUT_DIR,
+        Arc::clone(&output),
+        config.themes.clone(),
+        highlighting.theme_set,
+    )?;
+    let feed_creator = FeedCreator::new(
+        DEFAULT_OUT_DIR,
+        Arc::clone(&output),
+        now,
+        config.metadata.clone(),
+        config.feed.clone(),
+    );
     let renderer = Renderer::new(
         DEFAULT_IN_DIR,
         DEFAULT_OUT_DIR,
+        output,
+        cache,
+        templates,
         md,
         css_creator,
         feed_creator,
-        config.metadata.clone(),
+        config,
         year,
-        env_or_default("ANALYTICS_TAG", "dev_tag"),
+        analytics,
+        mode,
+        jobs,
     );
 
-    renderer.render().map_err(|e| {
-        eprintln!("{}", e);
-        e
-    })
+    renderer.render()
 }
 
-fn env_or_default(key: &str, default: &str) -> String {
-    env::var(key).unwrap_or(String::from(default))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaffolded_site_builds() {
+        let dir = env::temp_dir().join(format!("kblog-scaffold-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        scaffold::new_site(&dir, CONFIG_FILE_NAME, DEFAULT_IN_DIR).unwrap();
+        // The only test that changes directory, since builds work in the
+        // current one.
+        env::set_current_dir(&dir).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        scaffold::new_post(DEFAULT_IN_DIR, "Hello World", date).unwrap();
+        scaffold::new_draft(DEFAULT_IN_DIR, "Next Up").unwrap();
+        let built = build(Mode::Check, 1);
+        fs::remove_dir_all(&dir).unwrap();
+        built.unwrap();
+    }
 }
@@ -1,153 +1,1367 @@
-use anyhow::{anyhow, Result};
+use crate::config;
+use crate::document;
+use crate::math;
+use anyhow::{anyhow, bail, Error, Result};
+use pulldown_cmark::escape::escape_html;
 use pulldown_cmark::CodeBlockKind::Fenced;
 use pulldown_cmark::Event::{End, Html, Start, Text};
-use pulldown_cmark::Tag::{CodeBlock, FootnoteDefinition, Paragraph};
-use pulldown_cmark::{html, CowStr, Event, Options, Parser};
+use pulldown_cmark::Tag::{
+    BlockQuote, CodeBlock, FootnoteDefinition, Heading, Image, Link, Paragraph,
+};
+use pulldown_cmark::{html, CowStr, Event, HeadingLevel, Options, Parser};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::ops::Range;
 use syntect::html::{ClassStyle, ClassedHTMLGenerator};
 use syntect::parsing::{SyntaxReference, SyntaxSet};
 use syntect::util::LinesWithEndings;
 
 pub struct Markdowner {
     syntax_set: SyntaxSet,
+    config: config::Markdown,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+    /// Problems that didn't stop the markdown from being rendered.
+    pub warnings: Vec<MarkdownError>,
+}
+
+/// A heading in a table of contents, along with the headings nested beneath
+/// it.
+#[derive(Serialize, Deserialize)]
+pub struct TocEntry {
+    pub level: u32,
+    pub id: String,
+    pub title: String,
+    pub children: Vec<TocEntry>,
 }
 
 impl Markdowner {
-    pub fn new(syntax_set: SyntaxSet) -> Markdowner {
-        Markdowner { syntax_set }
+    pub fn new(syntax_set: SyntaxSet, config: config::Markdown) -> Markdowner {
+        Markdowner { syntax_set, config }
     }
 
-    pub fn to_html(&self, markdown: &str) -> Result<String> {
+    pub fn to_html(&self, markdown: &str) -> Result<RenderedMarkdown> {
         let mut rendered_content = String::new();
-        let parser = Parser::new_ext(
-            markdown,
-            Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH,
+        let parser = Parser::new_ext(markdown, parser_options());
+        let mut code_handler = CodeHandler::new(
+            &self.syntax_set,
+            &self.config.languages,
+            self.config.strict_languages,
         );
-        let mut code_handler = CodeHandler::new(&self.syntax_set);
-        let mut footnote_handler = FootnoteHandler::new();
-        let events = parser
-            .flat_map(|e| footnote_handler.handle_event(e))
-            .map(|e| code_handler.handle_event(e))
-            .collect::<Result<Vec<_>>>()?
-            .into_iter();
-        html::push_html(&mut rendered_content, events);
-        Ok(rendered_content)
+        let mut footnote_handler = FootnoteHandler::new(self.config.sidenotes);
+        let mut heading_handler = HeadingHandler::new(self.config.heading_permalinks);
+        let mut callout_handler = CalloutHandler::new();
+        let mut math_handler = MathHandler::new(markdown, self.config.math);
+        let mut errors = vec![];
+        let mut warnings = vec![];
+        let events: Vec<(Event, Range<usize>)> = parser
+            .into_offset_iter()
+            .flat_map(|(e, range)| {
+                let events = math_handler.handle_event(e, &range).unwrap_or_else(|err| {
+                    errors.push(err);
+                    vec![]
+                });
+                with_range(events, range)
+            })
+            .collect();
+        let events = footnote_handler.handle_events(events);
+        for (label, offset) in footnote_handler.take_undefined() {
+            let warning = anyhow!("footnote {} is never defined", label);
+            warnings.push(MarkdownError::at_offset(markdown, offset, warning));
+        }
+        let events: Vec<Event> = events
+            .into_iter()
+            .flat_map(|(e, range)| with_range(heading_handler.handle_event(e), range))
+            .flat_map(|(e, range)| with_range(callout_handler.handle_event(e), range))
+            .flat_map(|(e, range)| {
+                let result = code_handler.handle_event(e);
+                for warning in code_handler.take_warnings() {
+                    warnings.push(MarkdownError::at_offset(markdown, range.start, warning));
+                }
+                result.unwrap_or_else(|err| {
+                    errors.push(MarkdownError::at_offset(markdown, range.start, err));
+                    vec![]
+                })
+            })
+            .collect();
+        if !errors.is_empty() {
+            errors.sort_by_key(|e| (e.line, e.column));
+            bail!(MarkdownErrors(errors))
+        }
+        warnings.sort_by_key(|w| (w.line, w.column));
+        html::push_html(&mut rendered_content, events.into_iter());
+        Ok(RenderedMarkdown {
+            html: rendered_content,
+            toc: heading_handler.into_toc(),
+            warnings,
+        })
+    }
+}
+
+/// An error at a particular spot in a piece of markdown. Lines and columns
+/// both start at 1.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MarkdownError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl MarkdownError {
+    pub fn at_offset(markdown: &str, offset: usize, error: Error) -> MarkdownError {
+        let before = &markdown[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        MarkdownError {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            message: format!("{:#}", error),
+        }
+    }
+}
+
+impl Display for MarkdownError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for MarkdownError {}
+
+/// Every error found in a piece of markdown.
+#[derive(Debug)]
+pub struct MarkdownErrors(pub Vec<MarkdownError>);
+
+impl Display for MarkdownErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let messages: Vec<String> = self.0.iter().map(|e| e.to_string()).collect();
+        write!(f, "{}", messages.join("\n"))
+    }
+}
+
+impl std::error::Error for MarkdownErrors {}
+
+/// A link or image in a piece of markdown.
+pub struct Reference {
+    pub url: String,
+    pub is_image: bool,
+    pub offset: usize,
+}
+
+/// Finds every link and image in `markdown`, including those written as raw
+/// `<a>` and `<img>` tags.
+pub fn references(markdown: &str) -> Vec<Reference> {
+    let mut references = vec![];
+    for (event, range) in Parser::new_ext(markdown, parser_options()).into_offset_iter() {
+        match event {
+            Start(Link(_, url, _)) => references.push(Reference {
+                url: url.to_string(),
+                is_image: false,
+                offset: range.start,
+            }),
+            Start(Image(_, url, _)) => references.push(Reference {
+                url: url.to_string(),
+                is_image: true,
+                offset: range.start,
+            }),
+            Html(html) => {
+                references.extend(html_references(&html, "href", false, range.start));
+                references.extend(html_references(&html, "src", true, range.start));
+            }
+            _ => {}
+        }
+    }
+    references
+}
+
+fn html_references(html: &str, attribute: &str, is_image: bool, offset: usize) -> Vec<Reference> {
+    let mut references = vec![];
+    for quote in ['"', '\''] {
+        let prefix = format!("{}={}", attribute, quote);
+        for (start, _) in html.match_indices(&prefix) {
+            let value_start = start + prefix.len();
+            if let Some(len) = html[value_start..].find(quote) {
+                references.push(Reference {
+                    url: html[value_start..value_start + len].to_string(),
+                    is_image,
+                    offset: offset + start,
+                });
+            }
+        }
+    }
+    references
+}
+
+fn parser_options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH
+}
+
+fn with_range<'e>(
+    events: Vec<Event<'e>>,
+    range: Range<usize>,
+) -> impl Iterator<Item = (Event<'e>, Range<usize>)> {
+    events.into_iter().map(move |e| (e, range.clone()))
+}
+
+/// Renders `$...$` inline and `$$...$$` display math as MathML. Formulas are
+/// found in the markdown itself rather than in the parsed text, so that
+/// characters like `_` and `*` in them aren't taken as emphasis.
+struct MathHandler<'m> {
+    markdown: &'m str,
+    enabled: bool,
+    in_code_block: bool,
+    /// Where each element that's currently open ends, innermost last. A
+    /// formula can't run past the end of the element it starts in.
+    open_ends: Vec<usize>,
+    /// The end of a formula that carries on past the event it started in.
+    /// Events up until then are part of the formula and are dropped.
+    formula_end: Option<usize>,
+    /// How many more elements were started than ended within the formula.
+    formula_depth: usize,
+}
+
+impl<'m> MathHandler<'m> {
+    fn new(markdown: &'m str, enabled: bool) -> MathHandler<'m> {
+        MathHandler {
+            markdown,
+            enabled,
+            in_code_block: false,
+            open_ends: vec![],
+            formula_end: None,
+            formula_depth: 0,
+        }
+    }
+
+    fn handle_event<'e>(
+        &mut self,
+        event: Event<'e>,
+        range: &Range<usize>,
+    ) -> Result<Vec<Event<'e>>, MarkdownError> {
+        if !self.enabled {
+            return Ok(vec![event]);
+        }
+        match &event {
+            Start(tag) => {
+                self.in_code_block |= matches!(tag, CodeBlock(_));
+                self.open_ends.push(range.end);
+            }
+            End(tag) => {
+                self.in_code_block &= !matches!(tag, CodeBlock(_));
+                self.open_ends.pop();
+            }
+            _ => {}
+        }
+
+        if let Some(end) = self.formula_end {
+            // The formula can't outlast the element it started in.
+            let ends_element = matches!(event, End(_)) && self.formula_depth == 0;
+            if !ends_element && range.end <= end {
+                match event {
+                    Start(_) => self.formula_depth += 1,
+                    End(_) => self.formula_depth = self.formula_depth.saturating_sub(1),
+                    _ => {}
+                }
+                return Ok(vec![]);
+            }
+            self.formula_end = None;
+            if !ends_element && range.start < end {
+                return match event {
+                    Text(text) if self.formula_depth == 0 && self.is_verbatim(&text, range) => {
+                        self.split_text(&text[end - range.start..], end)
+                    }
+                    _ => Err(MarkdownError::at_offset(
+                        self.markdown,
+                        end,
+                        anyhow!("math formula overlaps other markdown, try escaping it"),
+                    )),
+                };
+            }
+        }
+
+        match event {
+            Text(text) if !self.in_code_block && self.is_verbatim(&text, range) => {
+                self.split_text(&text, range.start)
+            }
+            event => Ok(vec![event]),
+        }
+    }
+
+    /// Whether `text` is exactly what's in the markdown, rather than, say, a
+    /// character that was escaped.
+    fn is_verbatim(&self, text: &str, range: &Range<usize>) -> bool {
+        &self.markdown[range.clone()] == text
+    }
+
+    /// Splits the formulas out of `text`, which starts at `offset` in the
+    /// markdown.
+    fn split_text<'e>(
+        &mut self,
+        text: &str,
+        offset: usize,
+    ) -> Result<Vec<Event<'e>>, MarkdownError> {
+        let mut events = vec![];
+        let mut pos = 0;
+        while let Some((open, display)) = self.find_opening(text, offset, pos) {
+            let delimiter = if display { "$$" } else { "$" };
+            let tex_start = offset + open + delimiter.len();
+            let limit = self
+                .open_ends
+                .last()
+                .copied()
+                .unwrap_or(self.markdown.len());
+            let Some(len) = find_closing(&self.markdown[tex_start..limit], display) else {
+                if display {
+                    return Err(MarkdownError::at_offset(
+                        self.markdown,
+                        offset + open,
+                        anyhow!("display math opened with $$ is never closed"),
+                    ));
+                }
+                // A lone dollar sign, as in a price.
+                events.push(Text(text[pos..open + 1].to_string().into()));
+                pos = open + 1;
+                continue;
+            };
+
+            if pos < open {
+                events.push(Text(text[pos..open].to_string().into()));
+            }
+            // Skip over the formula even if it's invalid, so its closing
+            // delimiter isn't taken to open another.
+            let end = tex_start + len + delimiter.len();
+            let ends_later = end > offset + text.len();
+            if ends_later {
+                self.formula_end = Some(end);
+                self.formula_depth = 0;
+            }
+            let tex = &self.markdown[tex_start..tex_start + len];
+            let mathml = math::to_mathml(tex, display).map_err(|err| {
+                MarkdownError::at_offset(
+                    self.markdown,
+                    offset + open,
+                    err.context("invalid math formula"),
+                )
+            })?;
+            events.push(Html(mathml.into()));
+            if ends_later {
+                return Ok(events);
+            }
+            pos = end - offset;
+        }
+        if pos < text.len() {
+            events.push(Text(text[pos..].to_string().into()));
+        }
+        Ok(events)
+    }
+
+    /// Finds the next `$` or `$$` in `text` from `from` on that opens a
+    /// formula. Inline formulas can't start with a space, so prices like
+    /// `$5 and $10` are left alone.
+    fn find_opening(&self, text: &str, offset: usize, from: usize) -> Option<(usize, bool)> {
+        for (i, c) in text[from..].char_indices() {
+            let i = from + i;
+            if c != '$' || self.markdown[..offset + i].ends_with('\\') {
+                continue;
+            }
+            let after = &self.markdown[offset + i + 1..];
+            if after.starts_with('$') {
+                return Some((i, true));
+            }
+            if after.starts_with(|c: char| !c.is_whitespace()) {
+                return Some((i, false));
+            }
+        }
+        None
     }
 }
 
+/// Finds how far into `markdown` the formula's closing delimiter is. The `$`
+/// closing an inline formula can't follow a space or be followed by a digit.
+fn find_closing(markdown: &str, display: bool) -> Option<usize> {
+    if display {
+        return markdown.find("$$");
+    }
+    markdown.char_indices().find_map(|(i, c)| {
+        let before = markdown[..i].chars().next_back()?;
+        let closes = c == '$'
+            && !before.is_whitespace()
+            && before != '\\'
+            && !markdown[i + 1..].starts_with(|c: char| c.is_ascii_digit());
+        closes.then_some(i)
+    })
+}
+
 struct CodeHandler<'a> {
     syntax_set: &'a SyntaxSet,
-    current_lang: Option<String>,
+    languages: &'a BTreeMap<String, String>,
+    strict: bool,
+    current_code: Option<FencedCode<'a>>,
+    warnings: Vec<Error>,
+}
+
+/// The code block currently being rendered, collected until it ends.
+struct FencedCode<'a> {
+    kind: CodeBlockKind<'a>,
+    info: CodeBlockInfo,
+    code: String,
+}
+
+/// How the code block currently being rendered is formatted.
+enum CodeBlockKind<'a> {
+    Highlighted(&'a SyntaxReference),
+    Plain,
+}
+
+/// The language and attributes given in a fenced code block's info string,
+/// e.g. `rust,linenos,hl_lines=3-5,title=src/main.rs`.
+#[derive(Default)]
+struct CodeBlockInfo {
+    lang: String,
+    linenos: bool,
+    /// Inclusive ranges of lines to highlight, counting from 1.
+    hl_lines: Vec<(usize, usize)>,
+    title: Option<String>,
+    /// Attributes kblog doesn't know about, like rustdoc's `ignore`.
+    unknown: Vec<String>,
+}
+
+impl CodeBlockInfo {
+    fn parse(info: &str) -> Result<CodeBlockInfo> {
+        let mut parsed = CodeBlockInfo::default();
+        for (i, attribute) in info.split(',').map(str::trim).enumerate() {
+            match attribute.split_once('=') {
+                None if attribute == "linenos" => parsed.linenos = true,
+                None if i == 0 => parsed.lang = attribute.to_string(),
+                Some(("hl_lines", ranges)) => parsed.hl_lines = parse_line_ranges(ranges)?,
+                Some(("title", title)) => parsed.title = Some(title.trim().to_string()),
+                _ => parsed.unknown.push(attribute.to_string()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Whether the block has to be broken up into lines.
+    fn by_line(&self) -> bool {
+        self.linenos || !self.hl_lines.is_empty()
+    }
+
+    fn is_highlighted(&self, line: usize) -> bool {
+        self.hl_lines
+            .iter()
+            .any(|&(start, end)| (start..=end).contains(&line))
+    }
+}
+
+/// Parses space separated line numbers and ranges, like `1 3-5`.
+fn parse_line_ranges(ranges: &str) -> Result<Vec<(usize, usize)>> {
+    ranges
+        .split_whitespace()
+        .map(|range| {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            match (start.parse(), end.parse()) {
+                (Ok(start), Ok(end)) if 0 < start && start <= end => Ok((start, end)),
+                _ => Err(invalid_line_range(range)),
+            }
+        })
+        .collect()
 }
 
 impl<'a> CodeHandler<'a> {
-    fn new(syntax_set: &'a SyntaxSet) -> CodeHandler<'a> {
+    fn new(
+        syntax_set: &'a SyntaxSet,
+        languages: &'a BTreeMap<String, String>,
+        strict: bool,
+    ) -> CodeHandler<'a> {
         CodeHandler {
             syntax_set,
-            current_lang: None,
+            languages,
+            strict,
+            current_code: None,
+            warnings: vec![],
         }
     }
 
-    fn handle_event<'e>(&mut self, event: Event<'e>) -> Result<Event<'e>> {
-        let event = match event {
-            Start(CodeBlock(Fenced(lang))) => self.start_fenced_code(lang),
-            End(CodeBlock(Fenced(_))) => self.end_fenced_code(),
-            Text(text) => self.handle_text(text)?,
-            _ => event,
+    fn handle_event<'e>(&mut self, event: Event<'e>) -> Result<Vec<Event<'e>>> {
+        let events = match event {
+            Start(CodeBlock(Fenced(info))) => self.start_fenced_code(&info)?,
+            End(CodeBlock(Fenced(_))) => self.end_fenced_code()?,
+            Text(text) => self.handle_text(text),
+            _ => vec![event],
         };
-        Ok(event)
+        Ok(events)
     }
 
-    fn start_fenced_code<'e>(&mut self, lang: CowStr<'e>) -> Event<'e> {
-        self.current_lang = Some(lang.to_string());
-        Html("<pre class=\"code\"><code>".into())
+    /// Takes the warnings raised by the last event handled.
+    fn take_warnings(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.warnings)
     }
 
-    fn end_fenced_code<'e>(&mut self) -> Event<'e> {
-        self.current_lang = None;
-        Html("</code></pre>".into())
+    fn start_fenced_code<'e>(&mut self, info: &str) -> Result<Vec<Event<'e>>> {
+        let info = CodeBlockInfo::parse(info)?;
+        for attribute in &info.unknown {
+            if self.strict {
+                bail!(unknown_code_attribute(attribute));
+            }
+            self.warnings.push(anyhow!(
+                "{}, ignoring it",
+                unknown_code_attribute(attribute)
+            ));
+        }
+        let kind = match self.syntax_for_lang(&info.lang) {
+            Some(syntax) => CodeBlockKind::Highlighted(syntax),
+            None if info.lang.is_empty() => CodeBlockKind::Plain,
+            None if self.strict => bail!(unknown_language(&info.lang)),
+            None => {
+                self.warnings.push(anyhow!(
+                    "{}, rendering it as plain text",
+                    unknown_language(&info.lang)
+                ));
+                CodeBlockKind::Plain
+            }
+        };
+        self.current_code = Some(FencedCode {
+            kind,
+            info,
+            code: String::new(),
+        });
+        Ok(vec![])
     }
 
-    fn handle_text<'e>(&self, text: CowStr<'e>) -> Result<Event<'e>> {
-        if let Some(lang) = &self.current_lang {
-            self.code_html(&text, lang)
-        } else {
-            Ok(Event::Text(text))
+    fn end_fenced_code<'e>(&mut self) -> Result<Vec<Event<'e>>> {
+        let Some(block) = self.current_code.take() else {
+            return Ok(vec![]);
+        };
+        let lines = block.code.lines().count();
+        for &(start, end) in block.info.hl_lines.iter().filter(|&&(_, end)| end > lines) {
+            let error = line_range_past_end(start, end, lines);
+            if self.strict {
+                bail!(error);
+            }
+            self.warnings.push(anyhow!("{}, ignoring it", error));
         }
+        let mut body = match block.kind {
+            CodeBlockKind::Highlighted(syntax) => self.format_code(&block.code, syntax)?,
+            CodeBlockKind::Plain => {
+                let mut escaped = String::new();
+                escape_html(&mut escaped, &block.code)?;
+                escaped
+            }
+        };
+        if block.info.by_line() {
+            body = number_lines(&body, &block.info);
+        }
+
+        let mut html = String::new();
+        if let Some(title) = &block.info.title {
+            html.push_str("<figure class=\"code-block\"><figcaption>");
+            escape_html(&mut html, title)?;
+            html.push_str("</figcaption>");
+        }
+        html.push_str("<pre class=\"code\"><code>");
+        html.push_str(&body);
+        html.push_str("</code></pre>");
+        if block.info.title.is_some() {
+            html.push_str("</figure>");
+        }
+        html.push('\n');
+        Ok(vec![Html(html.into())])
     }
 
-    fn code_html<'e>(&self, code: &str, lang: &str) -> Result<Event<'e>> {
-        let formatted_code = self.format_code(code, lang)?;
-        Ok(Html(formatted_code.into()))
+    fn handle_text<'e>(&mut self, text: CowStr<'e>) -> Vec<Event<'e>> {
+        match &mut self.current_code {
+            Some(block) => {
+                block.code.push_str(&text);
+                vec![]
+            }
+            None => vec![Text(text)],
+        }
     }
 
-    fn format_code(&self, code: &str, lang: &str) -> Result<String> {
-        let mut html_generator = self.html_generator(lang)?;
+    fn format_code(&self, code: &str, syntax: &'a SyntaxReference) -> Result<String> {
+        let mut html_generator = self.html_generator(syntax);
         for line in LinesWithEndings::from(code) {
             html_generator.parse_html_for_line_which_includes_newline(line)?;
         }
         Ok(html_generator.finalize())
     }
 
-    fn html_generator(&self, lang: &str) -> Result<ClassedHTMLGenerator> {
-        let generator = ClassedHTMLGenerator::new_with_class_style(
-            self.syntax_for_lang(lang)?,
-            self.syntax_set,
-            ClassStyle::Spaced,
-        );
-        Ok(generator)
+    fn html_generator(&self, syntax: &'a SyntaxReference) -> ClassedHTMLGenerator<'a> {
+        ClassedHTMLGenerator::new_with_class_style(syntax, self.syntax_set, ClassStyle::Spaced)
     }
 
-    fn syntax_for_lang(&self, lang: &str) -> Result<&SyntaxReference> {
-        self.syntax_set
-            .find_syntax_by_extension(lang)
-            .ok_or(anyhow!("unknown language extension {lang}"))
+    /// Looks up a language by its alias in the config, or else by file
+    /// extension or name.
+    fn syntax_for_lang(&self, lang: &str) -> Option<&'a SyntaxReference> {
+        if lang.is_empty() {
+            return None;
+        }
+        let lang = self.languages.get(lang).map_or(lang, String::as_str);
+        self.syntax_set.find_syntax_by_token(lang)
     }
 }
 
-struct FootnoteHandler {
-    footnote_number_on_next_paragraph: bool,
-    footnote_counter: u32,
+/// Wraps each line of a code block's html in its own span, numbering and
+/// highlighting it as `info` asks. Spans that run across a line break are
+/// closed at the end of the line and opened again on the next one, so every
+/// line's html stands on its own.
+fn number_lines(html: &str, info: &CodeBlockInfo) -> String {
+    let mut numbered = String::new();
+    let mut open_spans: Vec<&str> = vec![];
+    // The generator closes its outermost span after the final newline, which
+    // would otherwise show up as an extra, empty line.
+    let trimmed = html.trim_end_matches("</span>");
+    let mut rest = trimmed.strip_suffix('\n').unwrap_or(trimmed);
+    let mut line_number = 1;
+    loop {
+        let (line, next) = match rest.find('\n') {
+            Some(end) => (&rest[..end], Some(&rest[end + 1..])),
+            None => (rest, None),
+        };
+        let class = if info.is_highlighted(line_number) {
+            "line hl"
+        } else {
+            "line"
+        };
+        numbered.push_str(&format!("<span class=\"{}\">", class));
+        if info.linenos {
+            numbered.push_str(&format!(
+                "<span class=\"line-number\">{}</span>",
+                line_number
+            ));
+        }
+        numbered.extend(open_spans.iter().copied());
+        track_spans(line, &mut open_spans);
+        numbered.push_str(line);
+        numbered.push_str(&"</span>".repeat(open_spans.len()));
+        numbered.push_str("\n</span>");
+
+        match next {
+            Some(next) => rest = next,
+            None => break,
+        }
+        line_number += 1;
+    }
+    numbered
+}
+
+/// Updates `open_spans` with the span tags `html` opens and closes.
+fn track_spans<'h>(html: &'h str, open_spans: &mut Vec<&'h str>) {
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+        let tag = &rest[..end];
+        if tag.starts_with("</") {
+            open_spans.pop();
+        } else {
+            open_spans.push(tag);
+        }
+        rest = &rest[end..];
+    }
+}
+
+fn unknown_language(lang: &str) -> Error {
+    anyhow!("unknown code block language {lang}")
+}
+
+fn unknown_code_attribute(attribute: &str) -> Error {
+    anyhow!("unknown code block attribute {attribute}")
 }
 
-impl FootnoteHandler {
-    fn new() -> FootnoteHandler {
+fn invalid_line_range(range: &str) -> Error {
+    anyhow!("invalid line range {range} in hl_lines")
+}
+
+fn line_range_past_end(start: usize, end: usize, lines: usize) -> Error {
+    let range = match start == end {
+        true => start.to_string(),
+        false => format!("{start}-{end}"),
+    };
+    anyhow!("line range {range} in hl_lines is past the last line, {lines}")
+}
+
+/// Numbers footnotes in the order they're first referenced and collects
+/// them at the end, each linking back to its references. With sidenotes, a
+/// footnote is also placed in the margin next to the block that first
+/// references it.
+struct FootnoteHandler<'e> {
+    sidenotes: bool,
+    definitions: BTreeMap<String, Footnote<'e>>,
+    /// Labels of the referenced footnotes, in the order of their numbers.
+    referenced: Vec<String>,
+    /// References to footnotes that were never defined, and where they are.
+    undefined: Vec<(String, usize)>,
+}
+
+struct Footnote<'e> {
+    events: Vec<(Event<'e>, Range<usize>)>,
+    /// The events again for the footnote's sidenote, where the references
+    /// it makes to other footnotes have no ids, so they aren't repeated.
+    sidenote_events: Vec<(Event<'e>, Range<usize>)>,
+    range: Range<usize>,
+    number: Option<usize>,
+    references: usize,
+}
+
+impl<'e> FootnoteHandler<'e> {
+    fn new(sidenotes: bool) -> FootnoteHandler<'e> {
         FootnoteHandler {
-            footnote_number_on_next_paragraph: false,
-            footnote_counter: 1,
+            sidenotes,
+            definitions: BTreeMap::new(),
+            referenced: vec![],
+            undefined: vec![],
         }
     }
 
-    fn handle_event<'e>(&mut self, event: Event<'e>) -> Vec<Event<'e>> {
+    fn handle_events(
+        &mut self,
+        events: Vec<(Event<'e>, Range<usize>)>,
+    ) -> Vec<(Event<'e>, Range<usize>)> {
+        let events = self.take_definitions(events);
+        let mut handled = vec![];
+        let mut depth = 0;
+        let mut pending_sidenotes = vec![];
+        // Sidenotes go just before the top level block that references them,
+        // so they float alongside it.
+        let mut block_start = 0;
+        for (event, range) in events {
+            match event {
+                Event::FootnoteReference(label) => {
+                    let numbered = self.referenced.len();
+                    let (event, _) = self.reference_events(&label, &range);
+                    handled.push((event, range));
+                    // Along with the footnotes it refers to in turn.
+                    if self.sidenotes {
+                        pending_sidenotes.extend(numbered + 1..=self.referenced.len());
+                    }
+                }
+                Start(_) => {
+                    if depth == 0 {
+                        block_start = handled.len();
+                    }
+                    depth += 1;
+                    handled.push((event, range));
+                }
+                End(_) => {
+                    depth -= 1;
+                    handled.push((event, range));
+                    if depth == 0 {
+                        let sidenotes: Vec<_> = pending_sidenotes
+                            .drain(..)
+                            .flat_map(|number| self.sidenote(number))
+                            .collect();
+                        handled.splice(block_start..block_start, sidenotes);
+                    }
+                }
+                _ => handled.push((event, range)),
+            }
+        }
+        handled.extend(self.footnotes());
+        handled
+    }
+
+    /// Takes the references to footnotes that were never defined, along
+    /// with their offsets in the markdown.
+    fn take_undefined(&mut self) -> Vec<(String, usize)> {
+        std::mem::take(&mut self.undefined)
+    }
+
+    /// Pulls the footnote definitions out of `events`.
+    fn take_definitions(
+        &mut self,
+        events: Vec<(Event<'e>, Range<usize>)>,
+    ) -> Vec<(Event<'e>, Range<usize>)> {
+        let mut rest = vec![];
+        let mut current: Option<(String, Footnote<'e>)> = None;
+        for (event, range) in events {
+            match (event, &mut current) {
+                (Start(FootnoteDefinition(label)), _) => {
+                    let footnote = Footnote {
+                        events: vec![],
+                        sidenote_events: vec![],
+                        range,
+                        number: None,
+                        references: 0,
+                    };
+                    current = Some((label.to_string(), footnote));
+                }
+                (End(FootnoteDefinition(_)), _) => {
+                    if let Some((label, footnote)) = current.take() {
+                        self.definitions.entry(label).or_insert(footnote);
+                    }
+                }
+                (event, Some((_, footnote))) => footnote.events.push((event, range)),
+                (event, None) => rest.push((event, range)),
+            }
+        }
+        rest
+    }
+
+    /// Turns a reference to the footnote `label` into the html for it in the
+    /// text and in a sidenote, or back into its markdown if there's no such
+    /// footnote.
+    fn reference_events(&mut self, label: &str, range: &Range<usize>) -> (Event<'e>, Event<'e>) {
+        if !self.definitions.contains_key(label) {
+            self.undefined.push((label.to_string(), range.start));
+            let text = Text(format!("[^{}]", label).into());
+            return (text.clone(), text);
+        }
+        let first = self.definitions[label].number.is_none();
+        let number = self.number(label);
+        let footnote = self.definitions.get_mut(label).unwrap();
+        footnote.references += 1;
+        let id = reference_name(number, footnote.references);
+        // Now that this reference is counted, so that references back to
+        // the footnote from within it come after this one.
+        if first {
+            self.resolve_references(label);
+        }
+        let html = reference_html(number, &format!(" id=\"fnref-{}\"", id));
+        (Html(html.into()), Html(reference_html(number, "").into()))
+    }
+
+    fn number(&mut self, label: &str) -> usize {
+        let footnote = self.definitions.get_mut(label).unwrap();
+        *footnote.number.get_or_insert_with(|| {
+            self.referenced.push(label.to_string());
+            self.referenced.len()
+        })
+    }
+
+    /// Numbers the footnotes the footnote `label` refers to, if it's the
+    /// first to, and gives it their html.
+    fn resolve_references(&mut self, label: &str) {
+        let footnote = self.definitions.get_mut(label).unwrap();
+        let content = std::mem::take(&mut footnote.events);
+        let mut events = vec![];
+        let mut sidenote_events = vec![];
+        for (event, range) in content {
+            let (event, sidenote_event) = match event {
+                Event::FootnoteReference(label) => self.reference_events(&label, &range),
+                event => (event.clone(), event),
+            };
+            events.push((event, range.clone()));
+            sidenote_events.push((sidenote_event, range));
+        }
+        let footnote = self.definitions.get_mut(label).unwrap();
+        footnote.events = events;
+        footnote.sidenote_events = sidenote_events;
+    }
+
+    fn sidenote(&self, number: usize) -> Vec<(Event<'e>, Range<usize>)> {
+        let footnote = &self.definitions[&self.referenced[number - 1]];
+        let range = footnote.range.clone();
+        let mut events = vec![(Html("<aside class=\"sidenote\">".into()), range.clone())];
+        let mut numbered = false;
+        for (event, event_range) in &footnote.sidenote_events {
+            events.push((event.clone(), event_range.clone()));
+            if !numbered && matches!(event, Start(Paragraph)) {
+                let number = format!("<span class=\"sidenote-number\">{}</span> ", number);
+                events.push((Html(number.into()), range.clone()));
+                numbered = true;
+            }
+        }
+        events.push((Html("</aside>\n".into()), range));
+        events
+    }
+
+    /// Lists the referenced footnotes in order, followed by any that were
+    /// never referenced.
+    fn footnotes(&mut self) -> Vec<(Event<'e>, Range<usize>)> {
+        let unreferenced: Vec<String> = self
+            .definitions
+            .iter()
+            .filter(|(_, footnote)| footnote.number.is_none())
+            .map(|(label, _)| label.clone())
+            .collect();
+        for label in unreferenced {
+            if self.definitions[&label].number.is_none() {
+                self.number(&label);
+                self.resolve_references(&label);
+            }
+        }
+        let labels = std::mem::take(&mut self.referenced);
+        let Some(first) = labels.first() else {
+            return vec![];
+        };
+
+        let class = if self.sidenotes {
+            "footnotes with-sidenotes"
+        } else {
+            "footnotes"
+        };
+        let start = self.definitions[first].range.clone();
+        let mut events = vec![(
+            Html(format!("<section class=\"{}\">\n<ol>\n", class).into()),
+            start.clone(),
+        )];
+        for (i, label) in labels.iter().enumerate() {
+            let number = i + 1;
+            let footnote = self.definitions.remove(label).unwrap();
+            let back_links = back_links(number, footnote.references);
+            events.push((
+                Html(format!("<li id=\"fn-{}\">\n", number).into()),
+                footnote.range.clone(),
+            ));
+            let ends_in_paragraph = matches!(footnote.events.last(), Some((End(Paragraph), _)));
+            let mut content = footnote.events;
+            // Back links go at the end of the last paragraph, if there is one.
+            let last = if ends_in_paragraph {
+                content.pop()
+            } else {
+                None
+            };
+            events.extend(content);
+            events.push((Html(back_links.into()), footnote.range.clone()));
+            events.extend(last);
+            events.push((Html("</li>\n".into()), footnote.range));
+        }
+        events.push((Html("</ol>\n</section>\n".into()), start));
+        events
+    }
+}
+
+/// The html for a reference to footnote `number`, with `id` as the html
+/// attribute that names it, if any.
+fn reference_html(number: usize, id: &str) -> String {
+    format!(
+        "<sup class=\"footnote-reference\"{}><a href=\"#fn-{}\">{}</a></sup>",
+        id, number, number
+    )
+}
+
+/// Names a footnote's references, e.g. `3` for the first reference to
+/// footnote 3 and `3-2` for the second.
+fn reference_name(number: usize, reference: usize) -> String {
+    match reference {
+        1 => number.to_string(),
+        _ => format!("{}-{}", number, reference),
+    }
+}
+
+/// Links from footnote `number` back to each of its `references`.
+fn back_links(number: usize, references: usize) -> String {
+    (1..=references)
+        .map(|reference| {
+            let name = reference_name(number, reference);
+            let marker = match reference {
+                1 => String::new(),
+                _ => format!("<sup>{}</sup>", reference),
+            };
+            format!(
+                " <a href=\"#fnref-{}\" class=\"footnote-back-link\" aria-label=\"Back to reference {}\">\u{21A9}{}</a>",
+                name, name, marker
+            )
+        })
+        .collect()
+}
+
+/// Turns blockquotes that start with `[!NOTE]`, `[!TIP]` or `[!WARNING]`
+/// into callouts, the way GitHub does. Any text after the marker is used as
+/// the callout's title.
+struct CalloutHandler<'e> {
+    /// The start of a blockquote that may be a callout, held back until the
+    /// end of its first line.
+    pending: Option<Vec<Event<'e>>>,
+    /// Whether each blockquote that's currently open is a callout, innermost
+    /// last.
+    open_quotes: Vec<bool>,
+}
+
+impl<'e> CalloutHandler<'e> {
+    fn new() -> CalloutHandler<'e> {
+        CalloutHandler {
+            pending: None,
+            open_quotes: vec![],
+        }
+    }
+
+    fn handle_event(&mut self, event: Event<'e>) -> Vec<Event<'e>> {
+        if let Some(pending) = &mut self.pending {
+            match event {
+                Start(Paragraph) if pending.len() == 1 => {
+                    pending.push(event);
+                    return vec![];
+                }
+                Text(_) if pending.len() > 1 => {
+                    pending.push(event);
+                    return vec![];
+                }
+                Event::SoftBreak | Event::HardBreak | End(Paragraph) if pending.len() > 2 => {
+                    return self.end_first_line(event);
+                }
+                _ => return self.not_a_callout(event),
+            }
+        }
         match event {
-            Start(FootnoteDefinition(label)) => self.start_footnote(label),
-            End(FootnoteDefinition(_)) => self.end_footnote(),
-            Start(Paragraph) => self.start_paragraph(),
+            Start(BlockQuote) => {
+                self.pending = Some(vec![event]);
+                vec![]
+            }
+            End(BlockQuote) if self.open_quotes.pop() == Some(true) => {
+                vec![Html("</aside>\n".into())]
+            }
             _ => vec![event],
         }
     }
 
-    fn start_footnote<'e>(&mut self, label: CowStr<'e>) -> Vec<Event<'e>> {
-        self.footnote_number_on_next_paragraph = true;
-        let open_div = format!("<div id=\"{}\" class=\"footnote\">", label);
-        vec![Html(CowStr::from(open_div))]
+    fn end_first_line(&mut self, event: Event<'e>) -> Vec<Event<'e>> {
+        let first_line = heading_text(self.pending.as_deref().unwrap_or_default());
+        let Some((kind, title)) = callout_marker(&first_line) else {
+            return self.not_a_callout(event);
+        };
+        self.pending = None;
+        self.open_quotes.push(true);
+
+        let mut title_html = String::new();
+        // Writing to a String can't fail.
+        let _ = escape_html(&mut title_html, title.unwrap_or(kind.title()));
+        let open_aside = format!(
+            "<aside class=\"callout callout-{}\"><p class=\"callout-title\"><span class=\"callout-icon\" aria-hidden=\"true\">{}</span> {}</p>\n",
+            kind.class(),
+            kind.icon(),
+            title_html
+        );
+        let mut events = vec![Html(open_aside.into())];
+        // The rest of the first paragraph is the start of the callout's body.
+        if !matches!(event, End(Paragraph)) {
+            events.push(Start(Paragraph));
+        }
+        events
+    }
+
+    /// Lets through the held back start of a blockquote that turned out to be
+    /// an ordinary one, followed by `event`.
+    fn not_a_callout(&mut self, event: Event<'e>) -> Vec<Event<'e>> {
+        let mut events = self.pending.take().unwrap_or_default();
+        self.open_quotes.push(false);
+        events.extend(self.handle_event(event));
+        events
     }
+}
+
+#[derive(Clone, Copy)]
+enum CalloutKind {
+    Note,
+    Tip,
+    Warning,
+}
 
-    fn end_footnote<'e>(&mut self) -> Vec<Event<'e>> {
-        vec![Event::Html(CowStr::from("</div>\n"))]
+impl CalloutKind {
+    fn class(self) -> &'static str {
+        match self {
+            CalloutKind::Note => "note",
+            CalloutKind::Tip => "tip",
+            CalloutKind::Warning => "warning",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            CalloutKind::Note => "Note",
+            CalloutKind::Tip => "Tip",
+            CalloutKind::Warning => "Warning",
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            CalloutKind::Note => "\u{2139}",
+            CalloutKind::Tip => "\u{1F4A1}",
+            CalloutKind::Warning => "\u{26A0}",
+        }
+    }
+}
+
+/// Parses a callout's first line, like `[!TIP] A custom title`, into its
+/// kind and title.
+fn callout_marker(line: &str) -> Option<(CalloutKind, Option<&str>)> {
+    let (marker, title) = line.trim().strip_prefix("[!")?.split_once(']')?;
+    let kind = match marker.to_ascii_lowercase().as_str() {
+        "note" => CalloutKind::Note,
+        "tip" => CalloutKind::Tip,
+        "warning" => CalloutKind::Warning,
+        _ => return None,
+    };
+    let title = Some(title.trim()).filter(|title| !title.is_empty());
+    Some((kind, title))
+}
+
+struct HeadingHandler<'e> {
+    permalinks: bool,
+    current_heading: Option<Vec<Event<'e>>>,
+    used_ids: HashSet<String>,
+    headings: Vec<(u32, String, String)>,
+}
+
+impl<'e> HeadingHandler<'e> {
+    fn new(permalinks: bool) -> HeadingHandler<'e> {
+        HeadingHandler {
+            permalinks,
+            current_heading: None,
+            used_ids: HashSet::new(),
+            headings: vec![],
+        }
     }
 
-    fn start_paragraph<'e>(&mut self) -> Vec<Event<'e>> {
-        let mut events = vec![Start(Paragraph)];
-        if self.footnote_number_on_next_paragraph {
-            events.push(self.footnote_number());
+    fn handle_event(&mut self, event: Event<'e>) -> Vec<Event<'e>> {
+        match (event, &mut self.current_heading) {
+            (Start(Heading(..)), _) => {
+                self.current_heading = Some(vec![]);
+                vec![]
+            }
+            (End(Heading(level, ..)), _) => self.end_heading(level),
+            (event, Some(contents)) => {
+                contents.push(event);
+                vec![]
+            }
+            (event, None) => vec![event],
         }
+    }
+
+    fn end_heading(&mut self, level: HeadingLevel) -> Vec<Event<'e>> {
+        let contents = self.current_heading.take().unwrap_or_default();
+        let title = heading_text(&contents);
+        let id = self.unique_id(&title);
+
+        let mut events = vec![Html(format!("<{} id=\"{}\">", level, id).into())];
+        events.extend(contents);
+        if self.permalinks {
+            let link = format!(
+                "<a class=\"anchor\" href=\"#{}\" aria-label=\"Link to this section\">#</a>",
+                id
+            );
+            events.push(Html(link.into()));
+        }
+        events.push(Html(format!("</{}>\n", level).into()));
+
+        self.headings.push((level as u32, id, title));
         events
     }
 
-    fn footnote_number<'e>(&mut self) -> Event<'e> {
-        let text = format!("[{}]: ", self.footnote_counter);
-        self.footnote_counter += 1;
-        self.footnote_number_on_next_paragraph = false;
-        Text(CowStr::from(text))
+    /// Slugifies the heading's text, adding a numeric suffix when an earlier
+    /// heading already took the same slug.
+    fn unique_id(&mut self, title: &str) -> String {
+        let base = match document::slugify(title) {
+            slug if slug.is_empty() => String::from("section"),
+            slug => slug,
+        };
+        let mut id = base.clone();
+        let mut suffix = 1;
+        while self.used_ids.contains(&id) {
+            id = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+        self.used_ids.insert(id.clone());
+        id
+    }
+
+    fn into_toc(self) -> Vec<TocEntry> {
+        let mut toc = vec![];
+        for (level, id, title) in self.headings {
+            insert_toc_entry(
+                &mut toc,
+                TocEntry {
+                    level,
+                    id,
+                    title,
+                    children: vec![],
+                },
+            );
+        }
+        toc
+    }
+}
+
+fn heading_text(events: &[Event]) -> String {
+    let mut text = String::new();
+    for event in events {
+        if let Text(t) | Event::Code(t) = event {
+            text.push_str(t);
+        }
+    }
+    text
+}
+
+/// Nests `entry` under the last entry in `entries` if it is a deeper heading.
+fn insert_toc_entry(entries: &mut Vec<TocEntry>, entry: TocEntry) {
+    match entries.last_mut() {
+        Some(last) if last.level < entry.level => insert_toc_entry(&mut last.children, entry),
+        _ => entries.push(entry),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_html(markdown: &str) -> Result<String> {
+        let markdowner = Markdowner::new(
+            SyntaxSet::load_defaults_newlines(),
+            config::Markdown {
+                math: true,
+                ..Default::default()
+            },
+        );
+        Ok(markdowner.to_html(markdown)?.html)
+    }
+
+    #[test]
+    fn math_is_off_by_default() {
+        let markdowner = Markdowner::new(
+            SyntaxSet::load_defaults_newlines(),
+            config::Markdown::default(),
+        );
+        let html = markdowner.to_html("echo $$ is $HOME/$USER").unwrap().html;
+        assert_eq!(html, "<p>echo $$ is $HOME/$USER</p>\n");
+    }
+
+    #[test]
+    fn inline_formula() {
+        let html = to_html("so $x^2$ here").unwrap();
+        assert!(html.starts_with("<p>so <math><semantics>"));
+        assert!(html.ends_with("</math> here</p>\n"));
+    }
+
+    #[test]
+    fn display_formula() {
+        let html = to_html("$$\nx = 1\n$$").unwrap();
+        assert!(html.contains("<math display=\"block\">"));
+        assert!(!html.contains('$'));
+    }
+
+    #[test]
+    fn prices_are_not_formulas() {
+        assert_eq!(to_html("$5 and $10").unwrap(), "<p>$5 and $10</p>\n");
+        assert_eq!(
+            to_html("costs $ 5 or 6$").unwrap(),
+            "<p>costs $ 5 or 6$</p>\n"
+        );
+    }
+
+    #[test]
+    fn escaped_dollars_are_not_formulas() {
+        assert_eq!(to_html("\\$x$ and \\$y$").unwrap(), "<p>$x$ and $y$</p>\n");
+    }
+
+    #[test]
+    fn formulas_in_code_are_left_alone() {
+        assert_eq!(to_html("`$x$`").unwrap(), "<p><code>$x$</code></p>\n");
+        let html = to_html("```\n$x$\n```").unwrap();
+        assert!(html.contains("$x$"));
+        assert!(!html.contains("<math"));
+    }
+
+    #[test]
+    fn formula_overlapping_markdown_fails() {
+        let err = to_html("$a *b$ c*").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "1:7: math formula overlaps other markdown, try escaping it"
+        );
+    }
+
+    #[test]
+    fn bad_formula_fails() {
+        let err = to_html("one\n\ntwo $\\foo$").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "3:5: invalid math formula: unknown command \\foo"
+        );
+    }
+
+    #[test]
+    fn footnotes_referenced_from_footnotes_are_numbered() {
+        let html = to_html("a[^x] b[^z]\n\n[^x]: see[^y]\n\n[^y]: y\n\n[^z]: z").unwrap();
+        assert!(html.contains(
+            "see<sup class=\"footnote-reference\" id=\"fnref-2\"><a href=\"#fn-2\">2</a></sup>"
+        ));
+        assert!(html.contains("<li id=\"fn-2\">\n<p>y <a href=\"#fnref-2\""));
+        assert!(html.contains("b<sup class=\"footnote-reference\" id=\"fnref-3\">"));
+        assert!(!html.contains("[^"));
+    }
+
+    #[test]
+    fn footnotes_referring_back_come_after_the_text() {
+        let html = to_html("a[^x]\n\n[^x]: see[^y]\n\n[^y]: back[^x]").unwrap();
+        assert!(html.contains("a<sup class=\"footnote-reference\" id=\"fnref-1\">"));
+        assert!(html.contains("back<sup class=\"footnote-reference\" id=\"fnref-1-2\">"));
+    }
+
+    #[test]
+    fn sidenotes_leave_out_reference_ids() {
+        let markdowner = Markdowner::new(
+            SyntaxSet::load_defaults_newlines(),
+            config::Markdown {
+                sidenotes: true,
+                ..Default::default()
+            },
+        );
+        let html = markdowner
+            .to_html("a[^x]\n\n[^x]: see[^y]\n\n[^y]: y")
+            .unwrap()
+            .html;
+        assert_eq!(html.matches("id=\"fnref-2\"").count(), 1);
+        assert_eq!(html.matches("<aside class=\"sidenote\">").count(), 2);
+    }
+
+    #[test]
+    fn code_block_info() {
+        let info =
+            CodeBlockInfo::parse("rust, linenos,hl_lines=1 3-5,title=src/main.rs,ignore").unwrap();
+        assert_eq!(info.lang, "rust");
+        assert!(info.linenos);
+        assert_eq!(info.hl_lines, [(1, 1), (3, 5)]);
+        assert_eq!(info.title.as_deref(), Some("src/main.rs"));
+        assert_eq!(info.unknown, ["ignore"]);
+        assert!(CodeBlockInfo::parse("rust,hl_lines=3-1").is_err());
+    }
+
+    #[test]
+    fn numbered_lines_reopen_spans() {
+        let html = to_html("```rust,linenos\nlet s = \"one\ntwo\";\n```").unwrap();
+        let lines: Vec<&str> = html.split('\n').collect();
+        assert!(lines[0].contains("<span class=\"line-number\">1</span>"));
+        assert!(lines[0].ends_with("one</span></span>"));
+        assert!(lines[1].starts_with(
+            "</span><span class=\"line\"><span class=\"line-number\">2</span>\
+             <span class=\"source rust\"><span class=\"string quoted double rust\">two"
+        ));
+        assert_eq!(lines[2], "</span></code></pre>");
+    }
+
+    #[test]
+    fn plain_lines_are_highlighted() {
+        assert_eq!(
+            to_html("```hl_lines=2\na\nb\n```").unwrap(),
+            "<pre class=\"code\"><code><span class=\"line\">a\n</span>\
+             <span class=\"line hl\">b\n</span></code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn highlighted_lines_past_the_end_warn() {
+        let markdowner = Markdowner::new(
+            SyntaxSet::load_defaults_newlines(),
+            config::Markdown::default(),
+        );
+        let rendered = markdowner.to_html("```hl_lines=2 5-6\na\nb\n```").unwrap();
+        let warnings: Vec<String> = rendered.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            ["1:1: line range 5-6 in hl_lines is past the last line, 2, ignoring it"]
+        );
+    }
+
+    #[test]
+    fn find_closing_skips_spaces_and_digits() {
+        assert_eq!(find_closing("x $5", false), None);
+        assert_eq!(find_closing("x$5 y$", false), Some(5));
+        assert_eq!(find_closing("a\\$b$", false), Some(4));
+        assert_eq!(find_closing("x $ $$", true), Some(4));
     }
 }
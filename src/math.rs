@@ -0,0 +1,968 @@
+use anyhow::{anyhow, bail, Error, Result};
+
+/// Renders a LaTeX formula as MathML. `display` formulas are set apart on a
+/// line of their own, the rest flow with the surrounding text.
+///
+/// Only the commonly used parts of LaTeX's math mode are supported: scripts,
+/// fractions, roots, accents, fonts, delimiters, matrices and the usual
+/// letters, symbols and function names.
+pub fn to_mathml(tex: &str, display: bool) -> Result<String> {
+    let mut parser = MathParser::new(tex);
+    let row = parser.parse_row()?;
+    if let Some(token) = parser.next_token()? {
+        bail!(unexpected(&token));
+    }
+    let display = if display { " display=\"block\"" } else { "" };
+    Ok(format!(
+        "<math{}><semantics><mrow>{}</mrow><annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        display,
+        row,
+        escape(tex.trim())
+    ))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Token<'t> {
+    Command(&'t str),
+    Char(char),
+    BeginGroup,
+    EndGroup,
+    Superscript,
+    Subscript,
+    /// `&`, which separates the cells of a table.
+    Align,
+    /// `\\`, which ends a row of a table.
+    NewRow,
+}
+
+/// A piece of a formula that scripts can be attached to.
+struct Atom {
+    mathml: String,
+    /// Scripts go above and below rather than to the side, as with `\sum`.
+    limits: bool,
+    /// Names a function, like `\sin`, so it is applied to what follows.
+    function: bool,
+}
+
+impl Atom {
+    fn new(mathml: String) -> Atom {
+        Atom {
+            mathml,
+            limits: false,
+            function: false,
+        }
+    }
+}
+
+/// The alphabets that `\mathbb` and friends switch letters and digits to.
+#[derive(Clone, Copy)]
+enum Variant {
+    Normal,
+    Bold,
+    Italic,
+    Script,
+    Fraktur,
+    DoubleStruck,
+    SansSerif,
+    Monospace,
+}
+
+struct MathParser<'t> {
+    tex: &'t str,
+    pos: usize,
+    variant: Option<Variant>,
+    /// How many `\sqrt[...]` indexes are being parsed, which end at `]`.
+    optional_depth: usize,
+}
+
+impl<'t> MathParser<'t> {
+    fn new(tex: &'t str) -> MathParser<'t> {
+        MathParser {
+            tex,
+            pos: 0,
+            variant: None,
+            optional_depth: 0,
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token<'t>>> {
+        let rest = &self.tex[self.pos..];
+        let trimmed = rest.trim_start();
+        self.pos += rest.len() - trimmed.len();
+        let mut chars = trimmed.chars();
+        let Some(c) = chars.next() else {
+            return Ok(None);
+        };
+        self.pos += c.len_utf8();
+        let token = match c {
+            '{' => Token::BeginGroup,
+            '}' => Token::EndGroup,
+            '^' => Token::Superscript,
+            '_' => Token::Subscript,
+            '&' => Token::Align,
+            '\\' => {
+                let rest = &self.tex[self.pos..];
+                let name_len = rest
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(rest.len());
+                let name_len = match rest.chars().next() {
+                    None => bail!("formula ends with a lone \\"),
+                    Some(_) if name_len > 0 => name_len,
+                    Some(c) => c.len_utf8(),
+                };
+                let name = &rest[..name_len];
+                self.pos += name_len;
+                if name == "\\" {
+                    Token::NewRow
+                } else {
+                    Token::Command(name)
+                }
+            }
+            c => Token::Char(c),
+        };
+        Ok(Some(token))
+    }
+
+    fn peek_token(&mut self) -> Result<Option<Token<'t>>> {
+        let pos = self.pos;
+        let token = self.next_token();
+        self.pos = pos;
+        token
+    }
+
+    /// Parses elements up until the end of the formula or of the group,
+    /// table cell or `\left` delimited row they're in.
+    fn parse_row(&mut self) -> Result<String> {
+        let mut row = String::new();
+        loop {
+            match self.peek_token()? {
+                None
+                | Some(Token::EndGroup | Token::Align | Token::NewRow)
+                | Some(Token::Command("right" | "end")) => break,
+                Some(Token::Char(']')) if self.optional_depth > 0 => break,
+                Some(_) => row.push_str(&self.parse_scripted()?),
+            }
+        }
+        Ok(row)
+    }
+
+    fn parse_scripted(&mut self) -> Result<String> {
+        let mut base = match self.peek_token()? {
+            Some(Token::Superscript | Token::Subscript) => Atom::new("<mrow></mrow>".into()),
+            _ => self.parse_atom()?,
+        };
+        // Primes are superscripts too, and can be followed by one more.
+        let mut superscripts: Vec<String> = vec![];
+        let mut has_superscript = false;
+        let mut subscript: Option<String> = None;
+        loop {
+            match self.peek_token()? {
+                Some(Token::Superscript) => {
+                    self.next_token()?;
+                    if has_superscript {
+                        bail!("double superscript");
+                    }
+                    has_superscript = true;
+                    superscripts.push(self.parse_script()?);
+                }
+                Some(Token::Subscript) => {
+                    self.next_token()?;
+                    if subscript.is_some() {
+                        bail!("double subscript");
+                    }
+                    subscript = Some(self.parse_script()?);
+                }
+                Some(Token::Char('\'')) => {
+                    self.next_token()?;
+                    superscripts.push(operator("\u{2032}"));
+                }
+                Some(Token::Command("limits")) => {
+                    self.next_token()?;
+                    base.limits = true;
+                }
+                Some(Token::Command("nolimits")) => {
+                    self.next_token()?;
+                    base.limits = false;
+                }
+                _ => break,
+            }
+        }
+
+        let (under, over) = if base.limits {
+            ("munder", "mover")
+        } else {
+            ("msub", "msup")
+        };
+        let superscript = match superscripts.len() {
+            0 => None,
+            1 => superscripts.pop(),
+            _ => Some(format!("<mrow>{}</mrow>", superscripts.concat())),
+        };
+        let mut mathml = match (subscript, superscript) {
+            (None, None) => base.mathml,
+            (Some(sub), None) => format!("<{under}>{}{sub}</{under}>", base.mathml),
+            (None, Some(sup)) => format!("<{over}>{}{sup}</{over}>", base.mathml),
+            (Some(sub), Some(sup)) => {
+                let both = if base.limits { "munderover" } else { "msubsup" };
+                format!("<{both}>{}{sub}{sup}</{both}>", base.mathml)
+            }
+        };
+        if base.function {
+            mathml.push_str("<mo>&#x2061;</mo>");
+        }
+        Ok(mathml)
+    }
+
+    fn parse_script(&mut self) -> Result<String> {
+        match self.peek_token()? {
+            None | Some(Token::EndGroup | Token::Align | Token::NewRow) => {
+                bail!("missing superscript or subscript")
+            }
+            _ => Ok(self.parse_atom()?.mathml),
+        }
+    }
+
+    /// Parses the required argument of `command`.
+    fn parse_arg(&mut self, command: &str) -> Result<String> {
+        match self.peek_token()? {
+            None | Some(Token::EndGroup | Token::Align | Token::NewRow) => {
+                Err(missing_argument(command))
+            }
+            _ => Ok(self.parse_atom()?.mathml),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Atom> {
+        let Some(token) = self.next_token()? else {
+            bail!("formula ends too soon");
+        };
+        let atom = match token {
+            Token::BeginGroup => {
+                let row = self.parse_row()?;
+                self.expect_end_group()?;
+                Atom::new(format!("<mrow>{}</mrow>", row))
+            }
+            Token::Char(c) if c.is_ascii_digit() => Atom::new(self.number(c)),
+            Token::Char(c) if c.is_alphabetic() => Atom::new(self.identifier(c)),
+            Token::Char('~') => Atom::new(space("0.333em")),
+            Token::Char('-') => Atom::new(operator("\u{2212}")),
+            Token::Char('*') => Atom::new(operator("\u{2217}")),
+            Token::Char('\'') => Atom::new(operator("\u{2032}")),
+            Token::Char(c) if is_operator(c) => Atom::new(operator(&c.to_string())),
+            Token::Command(name) => self.parse_command(name)?,
+            token => bail!(unexpected(&token)),
+        };
+        Ok(atom)
+    }
+
+    fn number(&mut self, first: char) -> String {
+        let mut number = first.to_string();
+        let rest = &self.tex[self.pos..];
+        let mut chars = rest.char_indices().peekable();
+        while let Some((_, c)) = chars.next() {
+            let continues = c.is_ascii_digit()
+                || (c == '.' && chars.peek().is_some_and(|(_, next)| next.is_ascii_digit()));
+            if !continues {
+                break;
+            }
+            number.push(c);
+            self.pos += c.len_utf8();
+        }
+        let number: String = number.chars().map(|c| self.styled(c)).collect();
+        format!("<mn>{}</mn>", escape(&number))
+    }
+
+    fn identifier(&self, c: char) -> String {
+        match self.variant {
+            Some(Variant::Normal) => {
+                format!("<mi mathvariant=\"normal\">{}</mi>", escape(&c.to_string()))
+            }
+            _ => format!("<mi>{}</mi>", escape(&self.styled(c).to_string())),
+        }
+    }
+
+    /// Switches `c` into the current variant's alphabet.
+    fn styled(&self, c: char) -> char {
+        self.variant.map_or(c, |variant| styled_char(c, variant))
+    }
+
+    fn parse_command(&mut self, name: &'t str) -> Result<Atom> {
+        if let Some(symbol) = symbol(name) {
+            return Ok(symbol);
+        }
+        let mathml = match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.parse_arg(name)?;
+                let denominator = self.parse_arg(name)?;
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let n = self.parse_arg(name)?;
+                let k = self.parse_arg(name)?;
+                format!(
+                    "<mrow>{}<mfrac linethickness=\"0\">{}{}</mfrac>{}</mrow>",
+                    fence("("),
+                    n,
+                    k,
+                    fence(")")
+                )
+            }
+            "sqrt" => {
+                let index = if self.peek_token()? == Some(Token::Char('[')) {
+                    self.next_token()?;
+                    self.optional_depth += 1;
+                    let index = self.parse_row()?;
+                    self.optional_depth -= 1;
+                    if self.next_token()? != Some(Token::Char(']')) {
+                        bail!("unclosed [ in \\sqrt");
+                    }
+                    Some(index)
+                } else {
+                    None
+                };
+                let radicand = self.parse_arg(name)?;
+                match index {
+                    Some(index) => format!("<mroot>{}<mrow>{}</mrow></mroot>", radicand, index),
+                    None => format!("<msqrt>{}</msqrt>", radicand),
+                }
+            }
+            "text" | "textrm" | "textnormal" | "mbox" => {
+                format!("<mtext>{}</mtext>", escape(&self.raw_group(name)?))
+            }
+            "textit" => format!(
+                "<mtext style=\"font-style: italic\">{}</mtext>",
+                escape(&self.raw_group(name)?)
+            ),
+            "textbf" => format!(
+                "<mtext style=\"font-weight: bold\">{}</mtext>",
+                escape(&self.raw_group(name)?)
+            ),
+            "operatorname" => format!("<mi>{}</mi>", escape(&self.raw_group(name)?)),
+            "mathrm" | "mathbf" | "mathit" | "mathcal" | "mathscr" | "mathfrak" | "mathbb"
+            | "mathsf" | "mathtt" | "boldsymbol" | "bm" => {
+                let variant = match name {
+                    "mathrm" => Variant::Normal,
+                    "mathbf" | "boldsymbol" | "bm" => Variant::Bold,
+                    "mathit" => Variant::Italic,
+                    "mathcal" | "mathscr" => Variant::Script,
+                    "mathfrak" => Variant::Fraktur,
+                    "mathbb" => Variant::DoubleStruck,
+                    "mathsf" => Variant::SansSerif,
+                    _ => Variant::Monospace,
+                };
+                let outer = self.variant.replace(variant);
+                let arg = self.parse_arg(name);
+                self.variant = outer;
+                arg?
+            }
+            "hat" | "widehat" | "bar" | "vec" | "tilde" | "widetilde" | "dot" | "ddot"
+            | "check" | "acute" | "grave" | "breve" => {
+                let accent = match name {
+                    "hat" | "widehat" => "^",
+                    "bar" => "\u{AF}",
+                    "vec" => "\u{2192}",
+                    "tilde" | "widetilde" => "~",
+                    "dot" => "\u{2D9}",
+                    "ddot" => "\u{A8}",
+                    "check" => "\u{2C7}",
+                    "acute" => "\u{B4}",
+                    "grave" => "`",
+                    _ => "\u{2D8}",
+                };
+                let stretchy = name.starts_with("wide");
+                format!(
+                    "<mover accent=\"true\">{}<mo stretchy=\"{}\">{}</mo></mover>",
+                    self.parse_arg(name)?,
+                    stretchy,
+                    escape(accent)
+                )
+            }
+            "overline" | "overbrace" => {
+                let line = if name == "overline" {
+                    "\u{203E}"
+                } else {
+                    "\u{23DE}"
+                };
+                format!(
+                    "<mover accent=\"true\">{}<mo stretchy=\"true\">{}</mo></mover>",
+                    self.parse_arg(name)?,
+                    line
+                )
+            }
+            "underline" | "underbrace" => {
+                let line = if name == "underline" { "_" } else { "\u{23DF}" };
+                format!(
+                    "<munder accentunder=\"true\">{}<mo stretchy=\"true\">{}</mo></munder>",
+                    self.parse_arg(name)?,
+                    line
+                )
+            }
+            "left" => {
+                let open = self.parse_delimiter(name)?;
+                let row = self.parse_row()?;
+                if self.next_token()? != Some(Token::Command("right")) {
+                    bail!("\\left without a matching \\right");
+                }
+                let close = self.parse_delimiter("right")?;
+                format!("<mrow>{}{}{}</mrow>", fence(open), row, fence(close))
+            }
+            "middle" => fence(self.parse_delimiter(name)?),
+            "big" | "bigl" | "bigr" | "bigm" | "Big" | "Bigl" | "Bigr" | "Bigm" | "bigg"
+            | "biggl" | "biggr" | "biggm" | "Bigg" | "Biggl" | "Biggr" | "Biggm" => {
+                let size = match name.trim_end_matches(['l', 'r', 'm']) {
+                    "big" => "1.2em",
+                    "Big" => "1.623em",
+                    "bigg" => "2.047em",
+                    _ => "2.470em",
+                };
+                format!(
+                    "<mo fence=\"true\" stretchy=\"true\" minsize=\"{size}\" maxsize=\"{size}\">{}</mo>",
+                    escape(self.parse_delimiter(name)?)
+                )
+            }
+            "begin" => self.parse_environment()?,
+            "not" => {
+                let negated = match self.next_token()? {
+                    Some(Token::Char('=')) => "\u{2260}".to_string(),
+                    Some(Token::Char(c)) if is_operator(c) => format!("{}\u{338}", c),
+                    Some(Token::Command(name)) => match symbol_char(name) {
+                        Some('∈') => "\u{2209}".to_string(),
+                        Some(c) => format!("{}\u{338}", c),
+                        None => bail!(unknown_command(name)),
+                    },
+                    _ => bail!(missing_argument("not")),
+                };
+                operator(&negated)
+            }
+            "mod" | "bmod" => format!("{}<mo>mod</mo>{}", space("0.5em"), space("0.5em")),
+            "pmod" => format!(
+                "{}<mrow><mo>(</mo><mo>mod</mo>{}{}<mo>)</mo></mrow>",
+                space("1em"),
+                space("0.333em"),
+                self.parse_arg(name)?
+            ),
+            "," | "thinspace" => space("0.167em"),
+            ":" | ">" | "medspace" => space("0.222em"),
+            ";" | "thickspace" => space("0.278em"),
+            " " => space("0.25em"),
+            "!" | "negthinspace" => space("-0.167em"),
+            "quad" => space("1em"),
+            "qquad" => space("2em"),
+            "displaystyle" | "textstyle" => String::new(),
+            "right" => bail!("\\right without a matching \\left"),
+            "end" => bail!("\\end without a matching \\begin"),
+            _ => bail!(unknown_command(name)),
+        };
+        Ok(Atom::new(mathml))
+    }
+
+    /// Reads the text of a group as is, for commands like `\text`.
+    fn raw_group(&mut self, command: &str) -> Result<String> {
+        if self.next_token()? != Some(Token::BeginGroup) {
+            bail!(missing_argument(command));
+        }
+        let rest = &self.tex[self.pos..];
+        let mut depth = 0;
+        for (i, c) in rest.char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => {
+                    self.pos += i + 1;
+                    return Ok(rest[..i].to_string());
+                }
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        bail!("unclosed {{ in \\{}", command)
+    }
+
+    /// Parses the delimiter following `\left`, `\right` and the like. `.`
+    /// stands for no delimiter at all.
+    fn parse_delimiter(&mut self, command: &str) -> Result<&'static str> {
+        let delimiter = match self.next_token()? {
+            Some(Token::Char('.')) => "",
+            Some(Token::Char('(')) => "(",
+            Some(Token::Char(')')) => ")",
+            Some(Token::Char('[')) => "[",
+            Some(Token::Char(']')) => "]",
+            Some(Token::Char('|')) => "|",
+            Some(Token::Char('/')) => "/",
+            Some(Token::Char('<')) => "\u{27E8}",
+            Some(Token::Char('>')) => "\u{27E9}",
+            Some(Token::Command(name)) => match name {
+                "{" | "lbrace" => "{",
+                "}" | "rbrace" => "}",
+                "|" | "Vert" | "lVert" | "rVert" => "\u{2016}",
+                "vert" | "lvert" | "rvert" => "|",
+                "langle" => "\u{27E8}",
+                "rangle" => "\u{27E9}",
+                "lfloor" => "\u{230A}",
+                "rfloor" => "\u{230B}",
+                "lceil" => "\u{2308}",
+                "rceil" => "\u{2309}",
+                "backslash" => "\\",
+                "uparrow" => "\u{2191}",
+                "downarrow" => "\u{2193}",
+                "updownarrow" => "\u{2195}",
+                _ => bail!("\\{} is not a delimiter", name),
+            },
+            _ => bail!("\\{} needs a delimiter", command),
+        };
+        Ok(delimiter)
+    }
+
+    fn parse_environment(&mut self) -> Result<String> {
+        let name = self.raw_group("begin")?;
+        let (open, close, columnalign) = match name.as_str() {
+            "matrix" | "smallmatrix" => ("", "", None),
+            "pmatrix" => ("(", ")", None),
+            "bmatrix" => ("[", "]", None),
+            "Bmatrix" => ("{", "}", None),
+            "vmatrix" => ("|", "|", None),
+            "Vmatrix" => ("\u{2016}", "\u{2016}", None),
+            "cases" => ("{", "", Some("left left")),
+            "aligned" | "align" | "align*" | "split" => ("", "", Some("right left")),
+            "gathered" | "gather" | "gather*" => ("", "", None),
+            _ => bail!("unknown environment {}", name),
+        };
+
+        let mut rows = vec![];
+        loop {
+            let mut cells = vec![self.parse_row()?];
+            while self.peek_token()? == Some(Token::Align) {
+                self.next_token()?;
+                cells.push(self.parse_row()?);
+            }
+            rows.push(cells);
+            match self.next_token()? {
+                Some(Token::NewRow) => {}
+                Some(Token::Command("end")) => {
+                    let end = self.raw_group("end")?;
+                    if end != name {
+                        bail!("\\begin{{{}}} ended by \\end{{{}}}", name, end);
+                    }
+                    break;
+                }
+                Some(token) => bail!(unexpected(&token)),
+                None => bail!("\\begin{{{}}} without a matching \\end", name),
+            }
+        }
+        // A trailing \\ doesn't start another row.
+        if rows.len() > 1
+            && rows
+                .last()
+                .is_some_and(|row| row.len() == 1 && row[0].is_empty())
+        {
+            rows.pop();
+        }
+
+        let mut table = match columnalign {
+            Some(align) => format!("<mtable columnalign=\"{}\">", align),
+            None => String::from("<mtable>"),
+        };
+        for row in rows {
+            table.push_str("<mtr>");
+            for cell in row {
+                table.push_str(&format!("<mtd>{}</mtd>", cell));
+            }
+            table.push_str("</mtr>");
+        }
+        table.push_str("</mtable>");
+        Ok(format!(
+            "<mrow>{}{}{}</mrow>",
+            fence(open),
+            table,
+            fence(close)
+        ))
+    }
+
+    fn expect_end_group(&mut self) -> Result<()> {
+        match self.next_token()? {
+            Some(Token::EndGroup) => Ok(()),
+            Some(token) => Err(unexpected(&token)),
+            None => bail!("unclosed {{"),
+        }
+    }
+}
+
+/// Looks up the letters, symbols and function names that stand alone.
+fn symbol(name: &str) -> Option<Atom> {
+    let function = |name: &str| Atom {
+        mathml: format!("<mi>{}</mi>", name),
+        limits: false,
+        function: true,
+    };
+    let limits_function = |name: &str| Atom {
+        mathml: format!("<mo movablelimits=\"true\" form=\"prefix\">{}</mo>", name),
+        limits: true,
+        function: false,
+    };
+    let atom = match name {
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
+        | "cosh" | "tanh" | "coth" | "log" | "ln" | "lg" | "exp" | "deg" | "dim" | "ker"
+        | "arg" | "hom" => function(name),
+        "lim" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr" => limits_function(name),
+        "liminf" => limits_function("lim&#x2006;inf"),
+        "limsup" => limits_function("lim&#x2006;sup"),
+        "sum" | "prod" | "coprod" | "bigcup" | "bigcap" | "bigoplus" | "bigotimes" | "bigvee"
+        | "bigwedge" => Atom {
+            mathml: format!(
+                "<mo largeop=\"true\" movablelimits=\"true\">{}</mo>",
+                symbol_char(name)?
+            ),
+            limits: true,
+            function: false,
+        },
+        "int" | "iint" | "iiint" | "oint" => {
+            Atom::new(format!("<mo largeop=\"true\">{}</mo>", symbol_char(name)?))
+        }
+        _ => {
+            let c = symbol_char(name)?;
+            let is_identifier = c.is_alphabetic()
+                || matches!(
+                    name,
+                    "infty" | "partial" | "nabla" | "emptyset" | "varnothing"
+                );
+            let mathml = if c.is_uppercase() && c.is_alphabetic() {
+                format!("<mi mathvariant=\"normal\">{}</mi>", c)
+            } else if is_identifier {
+                format!("<mi>{}</mi>", c)
+            } else {
+                operator(&c.to_string())
+            };
+            Atom::new(mathml)
+        }
+    };
+    Some(atom)
+}
+
+fn symbol_char(name: &str) -> Option<char> {
+    let c = match name {
+        "alpha" => 'α',
+        "beta" => 'β',
+        "gamma" => 'γ',
+        "delta" => 'δ',
+        "epsilon" => 'ϵ',
+        "varepsilon" => 'ε',
+        "zeta" => 'ζ',
+        "eta" => 'η',
+        "theta" => 'θ',
+        "vartheta" => 'ϑ',
+        "iota" => 'ι',
+        "kappa" => 'κ',
+        "lambda" => 'λ',
+        "mu" => 'μ',
+        "nu" => 'ν',
+        "xi" => 'ξ',
+        "pi" => 'π',
+        "varpi" => 'ϖ',
+        "rho" => 'ρ',
+        "varrho" => 'ϱ',
+        "sigma" => 'σ',
+        "varsigma" => 'ς',
+        "tau" => 'τ',
+        "upsilon" => 'υ',
+        "phi" => 'ϕ',
+        "varphi" => 'φ',
+        "chi" => 'χ',
+        "psi" => 'ψ',
+        "omega" => 'ω',
+        "Gamma" => 'Γ',
+        "Delta" => 'Δ',
+        "Theta" => 'Θ',
+        "Lambda" => 'Λ',
+        "Xi" => 'Ξ',
+        "Pi" => 'Π',
+        "Sigma" => 'Σ',
+        "Upsilon" => 'Υ',
+        "Phi" => 'Φ',
+        "Psi" => 'Ψ',
+        "Omega" => 'Ω',
+        "infty" => '∞',
+        "partial" => '∂',
+        "nabla" => '∇',
+        "ell" => 'ℓ',
+        "hbar" => 'ℏ',
+        "emptyset" | "varnothing" => '∅',
+        "aleph" => 'ℵ',
+        "Re" => 'ℜ',
+        "Im" => 'ℑ',
+        "wp" => '℘',
+        "prime" => '′',
+        "top" => '⊤',
+        "bot" | "perp" => '⊥',
+        "angle" => '∠',
+        "triangle" => '△',
+        "ldots" | "dots" => '…',
+        "cdots" => '⋯',
+        "vdots" => '⋮',
+        "ddots" => '⋱',
+        "pm" => '±',
+        "mp" => '∓',
+        "times" => '×',
+        "div" => '÷',
+        "cdot" => '⋅',
+        "ast" => '∗',
+        "star" => '⋆',
+        "circ" => '∘',
+        "bullet" => '∙',
+        "oplus" => '⊕',
+        "ominus" => '⊖',
+        "otimes" => '⊗',
+        "odot" => '⊙',
+        "cup" => '∪',
+        "cap" => '∩',
+        "setminus" | "backslash" => '∖',
+        "wedge" | "land" => '∧',
+        "vee" | "lor" => '∨',
+        "neg" | "lnot" => '¬',
+        "leq" | "le" => '≤',
+        "geq" | "ge" => '≥',
+        "neq" | "ne" => '≠',
+        "approx" => '≈',
+        "equiv" => '≡',
+        "sim" => '∼',
+        "simeq" => '≃',
+        "cong" => '≅',
+        "propto" => '∝',
+        "ll" => '≪',
+        "gg" => '≫',
+        "prec" => '≺',
+        "succ" => '≻',
+        "preceq" => '⪯',
+        "succeq" => '⪰',
+        "in" => '∈',
+        "notin" => '∉',
+        "ni" => '∋',
+        "subset" => '⊂',
+        "supset" => '⊃',
+        "subseteq" => '⊆',
+        "supseteq" => '⊇',
+        "parallel" => '∥',
+        "mid" => '∣',
+        "to" | "rightarrow" => '→',
+        "leftarrow" | "gets" => '←',
+        "leftrightarrow" => '↔',
+        "Rightarrow" => '⇒',
+        "Leftarrow" => '⇐',
+        "Leftrightarrow" => '⇔',
+        "longrightarrow" => '⟶',
+        "longleftarrow" => '⟵',
+        "implies" => '⟹',
+        "iff" => '⟺',
+        "mapsto" => '↦',
+        "uparrow" => '↑',
+        "downarrow" => '↓',
+        "forall" => '∀',
+        "exists" => '∃',
+        "nexists" => '∄',
+        "therefore" => '∴',
+        "because" => '∵',
+        "colon" => ':',
+        "vert" => '|',
+        "Vert" | "|" => '‖',
+        "langle" => '⟨',
+        "rangle" => '⟩',
+        "lfloor" => '⌊',
+        "rfloor" => '⌋',
+        "lceil" => '⌈',
+        "rceil" => '⌉',
+        "{" | "lbrace" => '{',
+        "}" | "rbrace" => '}',
+        "#" => '#',
+        "%" => '%',
+        "&" => '&',
+        "$" => '$',
+        "_" => '_',
+        "sum" => '∑',
+        "prod" => '∏',
+        "coprod" => '∐',
+        "bigcup" => '⋃',
+        "bigcap" => '⋂',
+        "bigoplus" => '⨁',
+        "bigotimes" => '⨂',
+        "bigvee" => '⋁',
+        "bigwedge" => '⋀',
+        "int" => '∫',
+        "iint" => '∬',
+        "iiint" => '∭',
+        "oint" => '∮',
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// Maps `c` to its counterpart in the Mathematical Alphanumeric Symbols
+/// block, minding the letters that were encoded elsewhere before it existed.
+fn styled_char(c: char, variant: Variant) -> char {
+    let exception = match (variant, c) {
+        (Variant::Italic, 'h') => Some('ℎ'),
+        (Variant::Script, 'B') => Some('ℬ'),
+        (Variant::Script, 'E') => Some('ℰ'),
+        (Variant::Script, 'F') => Some('ℱ'),
+        (Variant::Script, 'H') => Some('ℋ'),
+        (Variant::Script, 'I') => Some('ℐ'),
+        (Variant::Script, 'L') => Some('ℒ'),
+        (Variant::Script, 'M') => Some('ℳ'),
+        (Variant::Script, 'R') => Some('ℛ'),
+        (Variant::Script, 'e') => Some('ℯ'),
+        (Variant::Script, 'g') => Some('ℊ'),
+        (Variant::Script, 'o') => Some('ℴ'),
+        (Variant::Fraktur, 'C') => Some('ℭ'),
+        (Variant::Fraktur, 'H') => Some('ℌ'),
+        (Variant::Fraktur, 'I') => Some('ℑ'),
+        (Variant::Fraktur, 'R') => Some('ℜ'),
+        (Variant::Fraktur, 'Z') => Some('ℨ'),
+        (Variant::DoubleStruck, 'C') => Some('ℂ'),
+        (Variant::DoubleStruck, 'H') => Some('ℍ'),
+        (Variant::DoubleStruck, 'N') => Some('ℕ'),
+        (Variant::DoubleStruck, 'P') => Some('ℙ'),
+        (Variant::DoubleStruck, 'Q') => Some('ℚ'),
+        (Variant::DoubleStruck, 'R') => Some('ℝ'),
+        (Variant::DoubleStruck, 'Z') => Some('ℤ'),
+        _ => None,
+    };
+    if let Some(exception) = exception {
+        return exception;
+    }
+    // The first capital, small letter and digit of each alphabet.
+    let (upper, lower, digit) = match variant {
+        Variant::Normal => return c,
+        Variant::Bold => (0x1D400, 0x1D41A, Some(0x1D7CE)),
+        Variant::Italic => (0x1D434, 0x1D44E, None),
+        Variant::Script => (0x1D49C, 0x1D4B6, None),
+        Variant::Fraktur => (0x1D504, 0x1D51E, None),
+        Variant::DoubleStruck => (0x1D538, 0x1D552, Some(0x1D7D8)),
+        Variant::SansSerif => (0x1D5A0, 0x1D5BA, Some(0x1D7E2)),
+        Variant::Monospace => (0x1D670, 0x1D68A, Some(0x1D7F6)),
+    };
+    let code = match c {
+        'A'..='Z' => upper + (c as u32 - 'A' as u32),
+        'a'..='z' => lower + (c as u32 - 'a' as u32),
+        '0'..='9' => match digit {
+            Some(digit) => digit + (c as u32 - '0' as u32),
+            None => return c,
+        },
+        _ => return c,
+    };
+    char::from_u32(code).unwrap_or(c)
+}
+
+fn is_operator(c: char) -> bool {
+    matches!(
+        c,
+        '+' | '='
+            | '<'
+            | '>'
+            | '('
+            | ')'
+            | '['
+            | ']'
+            | ','
+            | ';'
+            | ':'
+            | '!'
+            | '/'
+            | '|'
+            | '.'
+            | '?'
+            | '@'
+    ) || (!c.is_alphanumeric() && !c.is_ascii() && !c.is_whitespace())
+}
+
+fn operator(op: &str) -> String {
+    format!("<mo>{}</mo>", escape(op))
+}
+
+fn fence(delimiter: &str) -> String {
+    if delimiter.is_empty() {
+        return String::new();
+    }
+    format!(
+        "<mo fence=\"true\" stretchy=\"true\">{}</mo>",
+        escape(delimiter)
+    )
+}
+
+fn space(width: &str) -> String {
+    format!("<mspace width=\"{}\"></mspace>", width)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unexpected(token: &Token) -> Error {
+    match token {
+        Token::Command(name) => anyhow!("unexpected \\{}", name),
+        Token::Char(c) => anyhow!("unexpected {}", c),
+        Token::BeginGroup => anyhow!("unexpected {{"),
+        Token::EndGroup => anyhow!("unexpected }}"),
+        Token::Superscript => anyhow!("unexpected ^"),
+        Token::Subscript => anyhow!("unexpected _"),
+        Token::Align => anyhow!("& outside of a matrix or aligned environment"),
+        Token::NewRow => anyhow!("\\\\ outside of a matrix or aligned environment"),
+    }
+}
+
+fn unknown_command(name: &str) -> Error {
+    anyhow!("unknown command \\{}", name)
+}
+
+fn missing_argument(command: &str) -> Error {
+    anyhow!("missing argument to \\{}", command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inline_formula() {
+        assert_eq!(
+            to_mathml("x^2", false).unwrap(),
+            "<math><semantics><mrow><msup><mi>x</mi><mn>2</mn></msup></mrow>\
+             <annotation encoding=\"application/x-tex\">x^2</annotation></semantics></math>"
+        );
+    }
+
+    #[test]
+    fn display_formula() {
+        let mathml = to_mathml("\\frac{a}{b}", true).unwrap();
+        assert!(mathml.starts_with("<math display=\"block\">"));
+        assert!(mathml.contains("<mfrac><mrow><mi>a</mi></mrow><mrow><mi>b</mi></mrow></mfrac>"));
+    }
+
+    #[test]
+    fn annotation_is_escaped() {
+        let mathml = to_mathml("a < b", false).unwrap();
+        assert!(mathml.contains(">a &lt; b</annotation>"));
+    }
+
+    #[test]
+    fn missing_argument_fails() {
+        let err = to_mathml("\\frac{a}", false).unwrap_err();
+        assert_eq!(err.to_string(), "missing argument to \\frac");
+    }
+
+    #[test]
+    fn unknown_command_fails() {
+        let err = to_mathml("\\foo", false).unwrap_err();
+        assert_eq!(err.to_string(), "unknown command \\foo");
+    }
+
+    #[test]
+    fn unmatched_left_fails() {
+        let err = to_mathml("\\left( x", false).unwrap_err();
+        assert_eq!(err.to_string(), "\\left without a matching \\right");
+    }
+
+    #[test]
+    fn unmatched_group_fails() {
+        assert!(to_mathml("{x", false).is_err());
+        assert!(to_mathml("x}", false).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Writes the generated site into the output directory, leaving files whose
+/// contents haven't changed untouched so their timestamps survive a rebuild.
+///
+/// A dry run output accepts every write but never touches the disk.
+pub struct Output {
+    root: PathBuf,
+    dry_run: bool,
+    written: Mutex<HashSet<PathBuf>>,
+}
+
+impl Output {
+    pub fn new<P: AsRef<Path>>(root: P, dry_run: bool) -> Output {
+        Output {
+            root: root.as_ref().to_path_buf(),
+            dry_run,
+            written: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P, contents: &[u8]) -> Result<()> {
+        let path = path.as_ref();
+        self.written.lock().unwrap().insert(path.to_path_buf());
+        if self.dry_run || fs::read(path).is_ok_and(|existing| existing == contents) {
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        self.write(to, &fs::read(from)?)
+    }
+
+    /// Deletes everything in the output directory that wasn't written during
+    /// this build.
+    pub fn remove_stale(&self) -> Result<()> {
+        if !self.dry_run && self.root.exists() {
+            let written = self.written.lock().unwrap();
+            remove_unwritten(&self.root, &written)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether `dir` is empty once the unwritten files have been removed.
+fn remove_unwritten(dir: &Path, written: &HashSet<PathBuf>) -> Result<bool> {
+    let mut is_empty = true;
+    for entry in dir.read_dir()? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if remove_unwritten(&path, written)? {
+                fs::remove_dir(&path)?;
+            } else {
+                is_empty = false;
+            }
+        } else if written.contains(&path) {
+            is_empty = false;
+        } else {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(is_empty)
+}
@@ -0,0 +1,155 @@
+use crate::document::{post_id, read_front_matter, RawDraft};
+use anyhow::{anyhow, Error, Result};
+use chrono::NaiveDate;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct Published {
+    pub path: PathBuf,
+    pub id: String,
+}
+
+/// Moves `draft` into the posts directory as a post published on `date`.
+/// `draft` can be the draft's name, its file name or a path to it, but it has
+/// to be in the drafts directory.
+pub fn publish<P: AsRef<Path>>(in_dir: P, draft: &str, date: NaiveDate) -> Result<Published> {
+    let drafts_dir = in_dir.as_ref().join("drafts");
+    let posts_dir = in_dir.as_ref().join("posts");
+    let draft_path = find_draft(&drafts_dir, draft)?;
+    let raw_draft = RawDraft::new(&draft_path)?;
+    let file_name = draft_path.file_name().unwrap().to_string_lossy();
+    // A post's id ends at the first _ after its date, so any in the draft's
+    // name would cut the id short.
+    let post_path = posts_dir.join(format!(
+        "{}_{}.md",
+        date.format("%Y-%m-%d"),
+        file_name.trim_end_matches(".md").replace('_', "-")
+    ));
+    let id = post_id(&post_path, &raw_draft.front_matter)?;
+
+    if posts_dir.exists() {
+        for entry in posts_dir.read_dir()? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "md") {
+                continue;
+            }
+            // Posts whose id can't be worked out are left for the build to
+            // report.
+            let Ok(existing) = read_front_matter(&path).and_then(|fm| post_id(&path, &fm)) else {
+                continue;
+            };
+            if existing == id {
+                return Err(existing_post(&id, &path));
+            }
+        }
+    }
+    if post_path.exists() {
+        return Err(existing_post(&id, &post_path));
+    }
+    fs::create_dir_all(&posts_dir)?;
+    fs::rename(&draft_path, &post_path)?;
+    Ok(Published {
+        path: post_path,
+        id,
+    })
+}
+
+fn find_draft(drafts_dir: &Path, draft: &str) -> Result<PathBuf> {
+    let as_path = PathBuf::from(draft);
+    if as_path.is_file() {
+        let in_drafts = drafts_dir.exists()
+            && fs::canonicalize(&as_path)?.starts_with(fs::canonicalize(drafts_dir)?);
+        if !in_drafts {
+            return Err(not_a_draft(&as_path, drafts_dir));
+        }
+        return Ok(as_path);
+    }
+    let in_drafts = drafts_dir.join(format!("{}.md", draft.trim_end_matches(".md")));
+    if in_drafts.is_file() {
+        return Ok(in_drafts);
+    }
+    Err(missing_draft(draft, drafts_dir))
+}
+
+fn missing_draft(draft: &str, drafts_dir: &Path) -> Error {
+    anyhow!("no draft named {} in {}", draft, drafts_dir.display())
+}
+
+fn not_a_draft(path: &Path, drafts_dir: &Path) -> Error {
+    anyhow!(
+        "{} isn't a draft, only drafts in {} can be published",
+        path.display(),
+        drafts_dir.display()
+    )
+}
+
+fn existing_post(id: &str, path: &Path) -> Error {
+    anyhow!(
+        "a post with the slug {} already exists at {}",
+        id,
+        path.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    /// A content directory holding a draft named `draft`, unique to `test`.
+    fn content_dir(test: &str, draft: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("kblog-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("drafts")).unwrap();
+        fs::create_dir_all(dir.join("posts")).unwrap();
+        fs::write(dir.join("drafts").join(draft), "Title\n\nSummary.\n").unwrap();
+        dir
+    }
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+    }
+
+    #[test]
+    fn underscores_are_kept_in_the_id() {
+        let dir = content_dir("underscores", "my_great_draft.md");
+        let published = publish(&dir, "my_great_draft", date()).unwrap();
+        assert_eq!(published.id, "my-great-draft");
+        assert_eq!(
+            published.path,
+            dir.join("posts").join("2024-03-01_my-great-draft.md")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_outside_drafts_are_refused() {
+        let dir = content_dir("outside", "draft.md");
+        let outside = dir.join("README.md");
+        fs::write(&outside, "Readme\n\nText.\n").unwrap();
+        let result = publish(&dir, outside.to_str().unwrap(), date());
+        assert!(result.is_err());
+        assert!(outside.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stray_files_in_posts_are_skipped() {
+        let dir = content_dir("stray", "draft.md");
+        fs::write(dir.join("posts").join(".DS_Store"), [0xff, 0xfe]).unwrap();
+        fs::write(dir.join("posts").join("2024-01-01_other.md"), "+++\nbroken").unwrap();
+        assert!(publish(&dir, "draft", date()).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn existing_ids_are_refused() {
+        let dir = content_dir("existing", "draft.md");
+        let existing = "+++\nslug = \"draft\"\n+++\nOld\n\nText.\n";
+        fs::write(dir.join("posts").join("2020-01-01_old.md"), existing).unwrap();
+        assert!(publish(&dir, "draft", date()).is_err());
+        assert!(dir.join("drafts").join("draft.md").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,17 +1,41 @@
-use crate::config::Metadata;
+use crate::cache::BuildCache;
+use crate::check::Checker;
+use crate::config::Config;
 use crate::css::CSSCreator;
-use crate::document::{RawDraft, RawPost, RenderedDraft, RenderedPost};
+use crate::document::{
+    ArchiveMonth, ArchiveYear, PostLink, RawDraft, RawPost, RenderedDraft, RenderedPost, Series,
+    Tag,
+};
 use crate::feed::FeedCreator;
-use crate::markdown::Markdowner;
-use crate::{css, feed, templates};
+use crate::markdown::{MarkdownError, Markdowner, RenderedMarkdown, TocEntry};
+use crate::output::Output;
+use crate::report::{ContentError, Report};
+use crate::templates::Templates;
+use crate::{check, css, document, serve, templates};
 use anyhow::{anyhow, bail, Context, Error, Result};
 use askama::Template;
+use chrono::{DateTime, Datelike, FixedOffset};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
 use std::cmp::Ordering;
-use std::fs::{self, File};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-const OG_TYPE_ARTICLE: &'static str = "article";
-const OG_TYPE_WEBSITE: &'static str = "website";
+const OG_TYPE_ARTICLE: &str = "article";
+const OG_TYPE_WEBSITE: &str = "website";
+const SITEMAP_FILE: &str = "sitemap.xml";
+const ROBOTS_FILE: &str = "robots.txt";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Build the site for publishing.
+    Build,
+    /// Build the site for local viewing with live reloading.
+    Serve,
+    /// Render everything and look for problems without writing the site.
+    Check,
+}
 
 pub struct Renderer {
     posts_in_dir: PathBuf,
@@ -21,24 +45,38 @@ pub struct Renderer {
     posts_out_dir: PathBuf,
     drafts_out_dir: PathBuf,
     imgs_out_dir: PathBuf,
+    tags_out_dir: PathBuf,
+    archive_out_dir: PathBuf,
+    output: Arc<Output>,
+    cache: BuildCache,
+    templates: Templates,
     markdowner: Markdowner,
     css_creator: CSSCreator,
     feed_creator: FeedCreator,
-    metadata: Metadata,
+    config: Config,
     year: String,
-    analytics_tag: String,
+    analytics: Option<templates::Analytics>,
+    mode: Mode,
+    jobs: usize,
+    warnings: Mutex<Vec<ContentError>>,
 }
 
 impl Renderer {
+    #[allow(clippy::too_many_arguments)]
     pub fn new<P>(
         in_dir: P,
         out_dir: P,
+        output: Arc<Output>,
+        cache: BuildCache,
+        templates: Templates,
         markdowner: Markdowner,
         css_creator: CSSCreator,
         feed_creator: FeedCreator,
-        metadata: Metadata,
+        config: Config,
         year: String,
-        analytics_tag: String,
+        analytics: Option<templates::Analytics>,
+        mode: Mode,
+        jobs: usize,
     ) -> Renderer
     where
         P: AsRef<Path>,
@@ -49,6 +87,8 @@ impl Renderer {
         let posts_out_dir = out_dir.as_ref().join("posts");
         let drafts_out_dir = out_dir.as_ref().join("drafts");
         let imgs_out_dir = out_dir.as_ref().join("imgs");
+        let tags_out_dir = out_dir.as_ref().join("tags");
+        let archive_out_dir = out_dir.as_ref().join("archive");
         let out_dir = out_dir.as_ref().to_path_buf();
         Renderer {
             posts_in_dir,
@@ -58,49 +98,91 @@ impl Renderer {
             posts_out_dir,
             drafts_out_dir,
             imgs_out_dir,
+            tags_out_dir,
+            archive_out_dir,
+            output,
+            cache,
+            templates,
             markdowner,
             css_creator,
             feed_creator,
-            metadata,
+            config,
             year,
-            analytics_tag,
+            analytics,
+            mode,
+            jobs,
+            warnings: Mutex::new(vec![]),
         }
     }
 
     pub fn render(&self) -> Result<()> {
-        let posts = self.render_posts()?;
-        let drafts = self.render_drafts()?;
-        self.reset_out_dir()?;
+        ThreadPoolBuilder::new()
+            .num_threads(self.jobs)
+            .build()?
+            .install(|| self.render_site())
+    }
+
+    fn render_site(&self) -> Result<()> {
+        let mut report = Report::default();
+        let (raw_posts, post_drafts) = self.read_posts(&mut report)?;
+        let raw_drafts = self.read_drafts(post_drafts, &mut report)?;
+        check::check_ids(&raw_posts, &raw_drafts, &mut report);
+        check::check_tag_slugs(&raw_posts, &mut report);
+        if self.mode == Mode::Check {
+            Checker::new(&self.imgs_in_dir, &self.config.metadata.domain).check(
+                &raw_posts,
+                &raw_drafts,
+                &mut report,
+            );
+        }
+        let posts = self.render_posts(raw_posts, &mut report);
+        let drafts = self.render_drafts(raw_drafts, &mut report);
+        for warning in self.warnings.lock().unwrap().drain(..) {
+            report.warn(warning);
+        }
+        if let Some(warnings) = report.warnings() {
+            eprintln!("{}", warnings);
+        }
+        if !report.is_empty() {
+            bail!(report)
+        }
+
         self.output_posts(&posts)?;
         self.output_drafts(&drafts)?;
         self.output_imgs()?;
         self.output_index(&posts)?;
+        self.output_tags(&posts)?;
+        self.output_archive(&posts)?;
         self.output_feed(&posts)?;
+        self.output_sitemap(&posts)?;
+        self.output_robots()?;
         self.output_css()?;
+        self.output.remove_stale()?;
+        self.cache.prune()?;
         Ok(())
     }
 
-    fn reset_out_dir(&self) -> Result<()> {
-        if self.out_dir.exists() {
-            fs::remove_dir_all(&self.out_dir)?;
-        }
-        fs::create_dir(&self.out_dir)?;
-        Ok(())
-    }
-
-    fn render_posts(&self) -> Result<Vec<RenderedPost>> {
+    /// Reads every post, splitting off the ones marked as drafts in their
+    /// front matter.
+    fn read_posts(&self, report: &mut Report) -> Result<(Vec<RawPost>, Vec<RawDraft>)> {
         if !self.posts_in_dir.exists() {
             bail!(missing_posts_dir(&self.posts_in_dir))
         }
 
-        let mut posts: Vec<RenderedPost> = self
-            .posts_in_dir
-            .read_dir()?
-            .map(|entry| RawPost::new(&entry?.path()))
-            .map(|raw_post| self.render_post(raw_post?))
-            .collect::<Result<Vec<RenderedPost>>>()?;
+        let paths = sorted_entries(&self.posts_in_dir)?;
+        let (drafts, posts): (Vec<RawPost>, Vec<RawPost>) =
+            par_map_or_report(paths, report, |path| {
+                RawPost::new(&path).map_err(|e| ContentError::new(&path, e))
+            })
+            .into_iter()
+            .partition(|post| post.front_matter.draft);
+        Ok((posts, drafts.into_iter().map(RawDraft::from).collect()))
+    }
+
+    fn render_posts(&self, raw_posts: Vec<RawPost>, report: &mut Report) -> Vec<RenderedPost> {
+        let mut posts = par_map_or_report(raw_posts, report, |raw_post| self.render_post(raw_post));
         posts.sort_by(Self::order_posts);
-        Ok(posts)
+        posts
     }
 
     fn order_posts(p1: &RenderedPost, p2: &RenderedPost) -> Ordering {
@@ -110,39 +192,91 @@ impl Renderer {
         }
     }
 
-    fn render_post(&self, post: RawPost) -> Result<RenderedPost> {
-        let html = self.markdowner.to_html(&post.markdown)?;
-        let summary = extract_summary(&html)
-            .with_context(|| format!("Failed to extract summary for \"{}\"", post.title))?;
+    fn render_post(&self, post: RawPost) -> Result<RenderedPost, ContentError> {
+        let rendered = self
+            .markdown_to_html(&post.markdown)
+            .map_err(|e| ContentError::in_markdown(&post.path, post.markdown_line, e))?;
+        self.warn(&post.path, post.markdown_line, rendered.warnings);
+        let toc = self.toc(post.front_matter.toc, rendered.toc);
+        let html = rendered.html;
+        let summary = match post.front_matter.description {
+            Some(description) => description,
+            None => extract_summary(&html)
+                .with_context(|| format!("Failed to extract summary for \"{}\"", post.title))
+                .map_err(|e| ContentError::new(&post.path, e))?,
+        };
         Ok(RenderedPost {
             id: post.id,
             title: post.title,
             date: post.date,
+            updated: post.updated,
+            tags: post.front_matter.tags,
             summary,
             html,
+            toc,
+            series: post.front_matter.series,
         })
     }
 
-    fn output_posts(&self, posts: &[RenderedPost]) -> Result<()> {
-        fs::create_dir(&self.posts_out_dir)?;
+    fn markdown_to_html(&self, markdown: &str) -> Result<RenderedMarkdown> {
+        self.cache
+            .rendered(markdown, || self.markdowner.to_html(markdown))
+    }
 
-        for post in posts {
-            self.output_post(&post)?;
+    /// Holds on to the warnings from rendering a file so they can all be
+    /// reported together once everything has been rendered.
+    fn warn(&self, path: &Path, markdown_line: usize, warnings: Vec<MarkdownError>) {
+        if !warnings.is_empty() {
+            let warning = ContentError::at_markdown(path, markdown_line, warnings);
+            self.warnings.lock().unwrap().push(warning);
         }
+    }
+
+    /// Returns the table of contents if it is turned on, either by the front
+    /// matter or by the config, and there are headings to list.
+    fn toc(&self, front_matter_toc: Option<bool>, toc: Vec<TocEntry>) -> Option<Vec<TocEntry>> {
+        let enabled = front_matter_toc.unwrap_or(self.config.markdown.table_of_contents);
+        (enabled && !toc.is_empty()).then_some(toc)
+    }
 
+    fn output_posts(&self, posts: &[RenderedPost]) -> Result<()> {
+        let series = group_by_series(posts);
+        try_par_map(posts.par_iter().enumerate(), |(i, post)| {
+            // Posts are sorted newest first, so the previous post comes after
+            // this one.
+            let prev = posts.get(i + 1);
+            let next = i.checked_sub(1).map(|i| &posts[i]);
+            let series = post.series.as_ref().map(|name| &series[name]);
+            self.output_post(post, prev, next, series)
+        })?;
         Ok(())
     }
 
-    fn output_post(&self, post: &RenderedPost) -> Result<()> {
+    fn output_post(
+        &self,
+        post: &RenderedPost,
+        prev: Option<&RenderedPost>,
+        next: Option<&RenderedPost>,
+        series: Option<&Series>,
+    ) -> Result<()> {
         let formatted_date = format!("{}", &post.date.format("%Y-%m-%d"));
-        let full_html = templates::PostTemplate {
+        let formatted_updated = post.updated.map(|u| format!("{}", u.format("%Y-%m-%d")));
+        let series_part = series
+            .and_then(|s| s.posts.iter().position(|p| p.id == post.id))
+            .map(|i| i + 1);
+        let full_html = self.templates.render(&templates::PostTemplate {
             title: &post.title,
             date: &formatted_date,
+            updated: formatted_updated.as_deref(),
+            tags: &post.tags,
+            toc: post.toc.as_deref(),
             content: &post.html,
-        }
-        .render()?;
+            prev: prev.map(PostLink::from),
+            next: next.map(PostLink::from),
+            series,
+            series_part,
+        })?;
         let post_dir = self.posts_out_dir.join(&post.id);
-        fs::create_dir(&post_dir)?;
         self.render_page(
             &post_dir.join("index.html"),
             &post.title,
@@ -153,50 +287,61 @@ impl Renderer {
         )
     }
 
-    fn render_drafts(&self) -> Result<Vec<RenderedDraft>> {
-        if !self.drafts_in_dir.exists() {
-            return Ok(vec![]);
+    /// Reads every draft, adding them to the posts that were marked as drafts.
+    fn read_drafts(
+        &self,
+        post_drafts: Vec<RawDraft>,
+        report: &mut Report,
+    ) -> Result<Vec<RawDraft>> {
+        let mut raw_drafts = post_drafts;
+        if self.drafts_in_dir.exists() {
+            let paths = sorted_entries(&self.drafts_in_dir)?;
+            raw_drafts.extend(par_map_or_report(paths, report, |path| {
+                RawDraft::new(&path).map_err(|e| ContentError::new(&path, e))
+            }));
         }
+        Ok(raw_drafts)
+    }
 
-        self.drafts_in_dir
-            .read_dir()?
-            .map(|entry| RawDraft::new(&entry?.path()))
-            .map(|raw_post| self.render_draft(raw_post?))
-            .collect::<Result<Vec<RenderedDraft>>>()
+    fn render_drafts(&self, raw_drafts: Vec<RawDraft>, report: &mut Report) -> Vec<RenderedDraft> {
+        par_map_or_report(raw_drafts, report, |raw_draft| self.render_draft(raw_draft))
     }
 
-    fn render_draft(&self, draft: RawDraft) -> Result<RenderedDraft> {
-        let html = self.markdowner.to_html(&draft.markdown)?;
+    fn render_draft(&self, draft: RawDraft) -> Result<RenderedDraft, ContentError> {
+        let rendered = self
+            .markdown_to_html(&draft.markdown)
+            .map_err(|e| ContentError::in_markdown(&draft.path, draft.markdown_line, e))?;
+        self.warn(&draft.path, draft.markdown_line, rendered.warnings);
         Ok(RenderedDraft {
             id: draft.id,
             title: draft.title,
-            html,
+            description: draft.front_matter.description,
+            html: rendered.html,
+            toc: self.toc(draft.front_matter.toc, rendered.toc),
         })
     }
 
     fn output_drafts(&self, drafts: &[RenderedDraft]) -> Result<()> {
-        fs::create_dir(&self.drafts_out_dir)?;
-
-        for draft in drafts {
-            self.output_draft(&draft)?;
-        }
+        try_par_map(drafts, |draft| self.output_draft(draft))?;
         Ok(())
     }
 
     fn output_draft(&self, draft: &RenderedDraft) -> Result<()> {
-        let full_html = templates::DraftTemplate {
+        let full_html = self.templates.render(&templates::DraftTemplate {
             title: &draft.title,
+            toc: draft.toc.as_deref(),
             content: &draft.html,
-        }
-        .render()?;
+        })?;
         let draft_dir = self.drafts_out_dir.join(&draft.id);
-        fs::create_dir(&draft_dir)?;
         self.render_page(
             &draft_dir.join("index.html"),
             &draft.title,
             &full_html,
             &format!("drafts/{}", draft.id),
-            &format!("{} draft post", draft.title),
+            &draft
+                .description
+                .clone()
+                .unwrap_or(format!("{} draft post", draft.title)),
             OG_TYPE_ARTICLE,
         )
     }
@@ -206,31 +351,152 @@ impl Renderer {
             return Ok(());
         }
 
-        fs::create_dir(&self.imgs_out_dir)?;
         for entry in self.imgs_in_dir.read_dir()? {
             let img = entry?;
-            fs::copy(img.path(), self.imgs_out_dir.join(img.file_name()))?;
+            self.output
+                .copy(img.path(), self.imgs_out_dir.join(img.file_name()))?;
         }
         Ok(())
     }
 
     fn output_index(&self, posts: &[RenderedPost]) -> Result<()> {
-        let index = templates::IndexTemplate {
-            blog_name: &self.metadata.blog_name,
-            blog_subtitle: &self.metadata.blog_subtitle,
-            posts,
-        }
-        .render()?;
+        let pages = paginate(posts, self.config.index.posts_per_page);
+        try_par_map(pages.par_iter().enumerate(), |(i, page)| {
+            self.output_index_page(i + 1, pages.len(), page)
+        })?;
+        Ok(())
+    }
+
+    fn output_index_page(
+        &self,
+        page: usize,
+        page_count: usize,
+        posts: &[RenderedPost],
+    ) -> Result<()> {
+        let metadata = &self.config.metadata;
+        let index = self.templates.render(&templates::IndexTemplate {
+            blog_name: &metadata.blog_name,
+            blog_subtitle: &metadata.blog_subtitle,
+            posts: posts.iter().map(PostLink::from).collect(),
+            summaries: self.config.index.summaries,
+            page,
+            page_count,
+            prev_url: (page > 1).then(|| format!("/{}", index_page_path(page - 1))),
+            next_url: (page < page_count).then(|| format!("/{}", index_page_path(page + 1))),
+        })?;
+        let title = match page {
+            1 => metadata.blog_name.clone(),
+            _ => format!("{} - Page {}", metadata.blog_name, page),
+        };
+        let path = index_page_path(page);
         self.render_page(
-            &self.out_dir.join("index.html"),
-            &self.metadata.blog_name,
+            &self.out_dir.join(&path).join("index.html"),
+            &title,
             &index,
-            &format!("https://{}/", &self.metadata.domain),
-            &self.metadata.blog_subtitle,
+            &self.to_og_url(&path)?,
+            &metadata.blog_subtitle,
             OG_TYPE_WEBSITE,
         )
     }
 
+    fn output_tags(&self, posts: &[RenderedPost]) -> Result<()> {
+        let tags = group_by_tag(posts);
+        try_par_map(&tags, |tag| self.output_tag(tag))?;
+
+        let overview = self
+            .templates
+            .render(&templates::TagsTemplate { tags: &tags })?;
+        self.render_page(
+            &self.tags_out_dir.join("index.html"),
+            "Tags",
+            &overview,
+            &self.to_og_url("tags/")?,
+            &format!("All tags on {}", self.config.metadata.blog_name),
+            OG_TYPE_WEBSITE,
+        )
+    }
+
+    fn output_tag(&self, tag: &Tag) -> Result<()> {
+        let tag_dir = self.tags_out_dir.join(&tag.slug);
+        let listing = self.templates.render(&templates::TagTemplate {
+            tag,
+            feeds: self.feed_creator.formats(),
+        })?;
+        self.render_page(
+            &tag_dir.join("index.html"),
+            &format!("Posts tagged \"{}\"", tag.name),
+            &listing,
+            &self.to_og_url(&format!("tags/{}/", tag.slug))?,
+            &format!(
+                "Posts tagged \"{}\" on {}",
+                tag.name, self.config.metadata.blog_name
+            ),
+            OG_TYPE_WEBSITE,
+        )?;
+        self.feed_creator.render_tag_feed(tag)
+    }
+
+    fn output_archive(&self, posts: &[RenderedPost]) -> Result<()> {
+        let years = group_by_month(posts);
+        try_par_map(&years, |year| {
+            self.output_archive_page(Some(year.year), std::slice::from_ref(year))
+        })?;
+        self.output_archive_page(None, &years)
+    }
+
+    fn output_archive_page(&self, year: Option<i32>, years: &[ArchiveYear]) -> Result<()> {
+        let archive = self
+            .templates
+            .render(&templates::ArchiveTemplate { year, years })?;
+        let (dir, url, title) = match year {
+            Some(year) => (
+                self.archive_out_dir.join(year.to_string()),
+                format!("archive/{}/", year),
+                format!("Posts from {}", year),
+            ),
+            None => (
+                self.archive_out_dir.clone(),
+                String::from("archive/"),
+                String::from("Archive"),
+            ),
+        };
+        self.render_page(
+            &dir.join("index.html"),
+            &title,
+            &archive,
+            &self.to_og_url(&url)?,
+            &format!("{} on {}", title, self.config.metadata.blog_name),
+            OG_TYPE_WEBSITE,
+        )
+    }
+
+    fn output_sitemap(&self, posts: &[RenderedPost]) -> Result<()> {
+        let mut urls = vec![templates::SitemapUrl {
+            loc: self.to_og_url("")?,
+            lastmod: posts.iter().map(last_modified).max().map(format_date),
+        }];
+        for post in posts {
+            urls.push(templates::SitemapUrl {
+                loc: self.to_og_url(&format!("posts/{}", post.id))?,
+                lastmod: Some(format_date(last_modified(post))),
+            });
+        }
+
+        let sitemap = templates::SitemapTemplate { urls: &urls }.render()?;
+        self.output
+            .write(self.out_dir.join(SITEMAP_FILE), sitemap.as_bytes())
+    }
+
+    fn output_robots(&self) -> Result<()> {
+        let robots = templates::RobotsTemplate {
+            disallow: &self.config.robots.disallow,
+            sitemap_url: &self.to_og_url(SITEMAP_FILE)?,
+        }
+        .render()?;
+        self.output
+            .write(self.out_dir.join(ROBOTS_FILE), robots.as_bytes())
+    }
+
     fn render_page(
         &self,
         path: &Path,
@@ -240,23 +506,33 @@ impl Renderer {
         og_description: &str,
         og_type: &str,
     ) -> Result<()> {
-        let mut file = File::create(path)?;
-        templates::PageTemplate {
+        let page = self.templates.render(&templates::PageTemplate {
             title,
             og_type,
             url,
-            blog_name: &self.metadata.blog_name,
+            blog_name: &self.config.metadata.blog_name,
             og_description,
-            feed_file: feed::FEED_FILE,
+            feeds: self.feed_creator.formats(),
             style: css::STYLE_FILE,
             body,
-            links: &self.metadata.links,
+            links: &self.config.metadata.links,
             year: &self.year,
-            author: &self.metadata.author,
-            analytics_tag: &self.analytics_tag,
+            author: &self.config.metadata.author,
+            analytics: self.analytics_for(path),
+            reload_path: (self.mode == Mode::Serve).then_some(serve::RELOAD_PATH),
+        })?;
+        self.output.write(path, page.as_bytes())
+    }
+
+    /// Picks the analytics for the page at `path`, leaving them out of drafts
+    /// and served pages unless the config asks for them.
+    fn analytics_for(&self, path: &Path) -> Option<&templates::Analytics> {
+        let config = &self.config.analytics;
+        let is_draft = path.starts_with(&self.drafts_out_dir);
+        if (is_draft && !config.drafts) || (self.mode == Mode::Serve && !config.serve) {
+            return None;
         }
-        .write_into(&mut file)?;
-        Ok(())
+        self.analytics.as_ref()
     }
 
     fn output_css(&self) -> Result<()> {
@@ -268,8 +544,144 @@ impl Renderer {
     }
 
     fn to_og_url(&self, path: &str) -> Result<String> {
-        Ok(format!("https://{}/{}", self.metadata.domain, path))
+        Ok(format!("https://{}/{}", self.config.metadata.domain, path))
+    }
+}
+
+fn last_modified(post: &RenderedPost) -> DateTime<FixedOffset> {
+    post.updated.unwrap_or(post.date)
+}
+
+fn format_date(date: DateTime<FixedOffset>) -> String {
+    format!("{}", date.format("%Y-%m-%d"))
+}
+
+/// Maps `f` over `items` on the current thread pool. Results come back in the
+/// same order as `items`, and the error returned is the one a serial loop
+/// would have stopped at.
+fn try_par_map<I, R, F>(items: I, f: F) -> Result<Vec<R>>
+where
+    I: IntoParallelIterator,
+    I::Iter: IndexedParallelIterator,
+    R: Send,
+    F: Fn(I::Item) -> Result<R> + Sync + Send,
+{
+    let results: Vec<Result<R>> = items.into_par_iter().map(f).collect();
+    results.into_iter().collect()
+}
+
+/// Like `try_par_map`, but rather than stopping at the first error it records
+/// every failure in `report` and returns the successes.
+fn par_map_or_report<T, R, F>(items: Vec<T>, report: &mut Report, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> Result<R, ContentError> + Sync + Send,
+{
+    let results: Vec<Result<R, ContentError>> = items.into_par_iter().map(f).collect();
+    let mut successes = Vec::with_capacity(results.len());
+    for result in results {
+        match result {
+            Ok(success) => successes.push(success),
+            Err(error) => report.add(error),
+        }
+    }
+    successes
+}
+
+/// Splits posts into pages of `per_page` posts, or a single page when it's 0.
+/// There is always at least one page, so the home page exists even when there
+/// are no posts.
+fn paginate(posts: &[RenderedPost], per_page: usize) -> Vec<&[RenderedPost]> {
+    if per_page == 0 || posts.is_empty() {
+        return vec![posts];
     }
+    posts.chunks(per_page).collect()
+}
+
+/// The path of a page of the index relative to the site root.
+fn index_page_path(page: usize) -> String {
+    match page {
+        1 => String::new(),
+        _ => format!("page/{}/", page),
+    }
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = dir
+        .read_dir()?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<PathBuf>>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+/// Groups posts by the slug of each of their tags, keeping the posts in their
+/// original order and the tags in alphabetical order. A post is only listed
+/// once under a tag, even if it gives the tag more than once.
+fn group_by_tag(posts: &[RenderedPost]) -> Vec<Tag<'_>> {
+    let mut tags: BTreeMap<String, Tag> = BTreeMap::new();
+    for post in posts {
+        let mut slugs = BTreeSet::new();
+        for name in &post.tags {
+            let slug = document::slugify(name);
+            if !slugs.insert(slug.clone()) {
+                continue;
+            }
+            tags.entry(slug.clone())
+                .or_insert_with(|| Tag {
+                    name: name.clone(),
+                    slug,
+                    posts: vec![],
+                })
+                .posts
+                .push(post);
+        }
+    }
+    tags.into_values().collect()
+}
+
+/// Groups posts by the series they belong to, ordering each series from its
+/// oldest post to its newest.
+fn group_by_series(posts: &[RenderedPost]) -> BTreeMap<String, Series<'_>> {
+    let mut series: BTreeMap<String, Series> = BTreeMap::new();
+    for post in posts.iter().rev() {
+        if let Some(name) = &post.series {
+            series
+                .entry(name.clone())
+                .or_insert_with(|| Series {
+                    name: name.clone(),
+                    posts: vec![],
+                })
+                .posts
+                .push(post);
+        }
+    }
+    series
+}
+
+/// Groups posts by the year and then the month they were published in,
+/// relying on the posts already being sorted newest first.
+fn group_by_month(posts: &[RenderedPost]) -> Vec<ArchiveYear<'_>> {
+    let mut years: Vec<ArchiveYear> = vec![];
+    for post in posts {
+        if years.last().map(|y| y.year) != Some(post.date.year()) {
+            years.push(ArchiveYear {
+                year: post.date.year(),
+                months: vec![],
+            });
+        }
+        let months = &mut years.last_mut().unwrap().months;
+        let name = format!("{}", post.date.format("%B"));
+        if months.last().map(|m| &m.name) != Some(&name) {
+            months.push(ArchiveMonth {
+                name,
+                posts: vec![],
+            });
+        }
+        months.last_mut().unwrap().posts.push(post);
+    }
+    years
 }
 
 fn extract_summary(summary_html: &str) -> Result<String> {
@@ -281,14 +693,33 @@ fn extract_summary(summary_html: &str) -> Result<String> {
     Ok(formatted)
 }
 
+/// Strips the tags out of `html`, leaving its text. Formulas are replaced by
+/// the TeX they were written in, since the text of their MathML reads poorly.
 fn strip_html(html: &str) -> String {
     let mut result = String::from("");
+    let mut tag = String::new();
     let mut in_tag = false;
+    let mut in_math = false;
+    let mut in_tex = false;
     for c in html.chars() {
         match c {
-            '<' => in_tag = true,
-            '>' => in_tag = false,
-            next if !in_tag => result.push(next),
+            '<' => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' => {
+                in_tag = false;
+                let name = tag.split_whitespace().next().unwrap_or_default();
+                match name {
+                    "math" => in_math = true,
+                    "/math" => in_math = false,
+                    "annotation" => in_tex = true,
+                    "/annotation" => in_tex = false,
+                    _ => {}
+                }
+            }
+            next if in_tag => tag.push(next),
+            next if !in_math || in_tex => result.push(next),
             _ => continue,
         }
     }
@@ -305,3 +736,40 @@ fn missing_posts_dir(path: &Path) -> Error {
 fn missing_summary() -> Error {
     anyhow!("no summary found")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(id: &str, tags: &[&str]) -> RenderedPost {
+        RenderedPost {
+            id: id.to_string(),
+            title: id.to_string(),
+            date: DateTime::parse_from_rfc3339("2024-03-01T00:00:00Z").unwrap(),
+            updated: None,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            summary: String::new(),
+            html: String::new(),
+            toc: None,
+            series: None,
+        }
+    }
+
+    #[test]
+    fn posts_are_listed_once_per_tag() {
+        let posts = [post("a", &["Rust", "rust", "Rust"]), post("b", &["rust"])];
+        let tags = group_by_tag(&posts);
+        assert_eq!(tags.len(), 1);
+        let ids: Vec<&str> = tags[0].posts.iter().map(|post| post.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+    }
+
+    #[test]
+    fn summary_shows_formulas_as_tex() {
+        let html = format!(
+            "<p>So {} here.</p><p>More</p>",
+            crate::math::to_mathml("\\frac{1}{2}", false).unwrap()
+        );
+        assert_eq!(extract_summary(&html).unwrap(), "So \\frac{1}{2} here.");
+    }
+}
@@ -0,0 +1,147 @@
+use crate::markdown::{MarkdownError, MarkdownErrors};
+use anyhow::Error;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// The problems found in a single post or draft.
+#[derive(Debug)]
+pub struct ContentError {
+    path: PathBuf,
+    problems: Vec<Problem>,
+}
+
+#[derive(Debug)]
+struct Problem {
+    position: Option<(usize, usize)>,
+    message: String,
+}
+
+impl ContentError {
+    pub fn new(path: &Path, error: Error) -> ContentError {
+        ContentError {
+            path: path.to_path_buf(),
+            problems: vec![Problem {
+                position: None,
+                message: format!("{:#}", error),
+            }],
+        }
+    }
+
+    /// Creates an error for the problems found while rendering the markdown of
+    /// a file, where the markdown begins on line `markdown_line` of the file.
+    pub fn in_markdown(path: &Path, markdown_line: usize, error: Error) -> ContentError {
+        match error.downcast::<MarkdownErrors>() {
+            Ok(MarkdownErrors(errors)) => ContentError::at_markdown(path, markdown_line, errors),
+            Err(error) => ContentError::new(path, error),
+        }
+    }
+
+    /// Like `in_markdown`, but for problems that have already been located.
+    pub fn at_markdown(
+        path: &Path,
+        markdown_line: usize,
+        errors: Vec<MarkdownError>,
+    ) -> ContentError {
+        ContentError {
+            path: path.to_path_buf(),
+            problems: errors
+                .into_iter()
+                .map(|e| Problem {
+                    position: Some((markdown_line + e.line - 1, e.column)),
+                    message: e.message,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Every problem found while rendering the site's content, grouped by file
+/// when displayed.
+#[derive(Debug, Default)]
+pub struct Report {
+    errors: Vec<ContentError>,
+    warnings: Vec<ContentError>,
+}
+
+impl Report {
+    pub fn add(&mut self, error: ContentError) {
+        self.errors.push(error);
+    }
+
+    /// Records problems that don't stop the site from being built.
+    pub fn warn(&mut self, warning: ContentError) {
+        self.warnings.push(warning);
+    }
+
+    /// Returns true when there are no errors, regardless of any warnings.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn warnings(&self) -> Option<Warnings<'_>> {
+        (!self.warnings.is_empty()).then_some(Warnings(&self.warnings))
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_problems(f, &self.errors, "error", "errors")
+    }
+}
+
+impl std::error::Error for Report {}
+
+/// The warnings in a report, grouped by file when displayed.
+pub struct Warnings<'r>(&'r [ContentError]);
+
+impl Display for Warnings<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_problems(f, self.0, "warning", "warnings")
+    }
+}
+
+fn write_problems(
+    f: &mut Formatter<'_>,
+    errors: &[ContentError],
+    singular: &str,
+    plural_name: &str,
+) -> fmt::Result {
+    let mut problems_by_path: BTreeMap<&Path, Vec<&Problem>> = BTreeMap::new();
+    for error in errors {
+        problems_by_path
+            .entry(&error.path)
+            .or_default()
+            .extend(&error.problems);
+    }
+
+    let problem_count = problems_by_path.values().map(Vec::len).sum();
+    write!(
+        f,
+        "found {} {} in {} {}",
+        problem_count,
+        plural(problem_count, singular, plural_name),
+        problems_by_path.len(),
+        plural(problems_by_path.len(), "file", "files"),
+    )?;
+
+    for (path, mut problems) in problems_by_path {
+        write!(f, "\n{}", path.display())?;
+        problems.sort_by_key(|p| p.position);
+        for problem in problems {
+            match problem.position {
+                Some((line, column)) => write!(f, "\n  {}:{}: {}", line, column, problem.message)?,
+                None => write!(f, "\n  {}", problem.message)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+fn plural<'a>(count: usize, singular: &'a str, plural: &'a str) -> &'a str {
+    if count == 1 {
+        singular
+    } else {
+        plural
+    }
+}
@@ -0,0 +1,81 @@
+use crate::document::slugify;
+use anyhow::{anyhow, Error, Result};
+use chrono::NaiveDate;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CONFIG: &str = include_str!("../templates/config.toml");
+const GITIGNORE: &str = "gen/\n.kblog-cache/\n";
+/// Posts need a first paragraph to summarize them by, so the site builds
+/// before anything has been written.
+const PLACEHOLDER: &str = "Summary goes here.\n";
+
+/// Creates a draft titled `title` in the drafts directory.
+pub fn new_draft<P: AsRef<Path>>(in_dir: P, title: &str) -> Result<PathBuf> {
+    let path = in_dir
+        .as_ref()
+        .join("drafts")
+        .join(format!("{}.md", slug(title)?));
+    create_document(&path, title)?;
+    Ok(path)
+}
+
+/// Creates a post titled `title`, published on `date`, in the posts
+/// directory.
+pub fn new_post<P: AsRef<Path>>(in_dir: P, title: &str, date: NaiveDate) -> Result<PathBuf> {
+    let path = in_dir.as_ref().join("posts").join(format!(
+        "{}_{}.md",
+        date.format("%Y-%m-%d"),
+        slug(title)?
+    ));
+    create_document(&path, title)?;
+    Ok(path)
+}
+
+/// Lays out a new site in `dir`, with a config file to fill in.
+pub fn new_site<P: AsRef<Path>>(dir: P, config_file: &str, in_dir: &str) -> Result<()> {
+    let dir = dir.as_ref();
+    let config_path = dir.join(config_file);
+    if config_path.exists() {
+        return Err(already_exists(&config_path));
+    }
+    for content_dir in ["posts", "drafts", "imgs"] {
+        fs::create_dir_all(dir.join(in_dir).join(content_dir))?;
+    }
+    fs::write(config_path, CONFIG)?;
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        fs::write(gitignore, GITIGNORE)?;
+    }
+    Ok(())
+}
+
+fn slug(title: &str) -> Result<String> {
+    let slug = slugify(title);
+    if slug.is_empty() {
+        return Err(empty_slug(title));
+    }
+    Ok(slug)
+}
+
+fn create_document(path: &Path, title: &str) -> Result<()> {
+    if path.exists() {
+        return Err(already_exists(path));
+    }
+    let document = format!(
+        "+++\ntitle = {}\n# description = \"\"\n# tags = []\n+++\n\n{}",
+        toml::Value::String(title.to_string()),
+        PLACEHOLDER
+    );
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, document)?;
+    Ok(())
+}
+
+fn already_exists(path: &Path) -> Error {
+    anyhow!("{} already exists", path.display())
+}
+
+fn empty_slug(title: &str) -> Error {
+    anyhow!("can't make a slug out of the title \"{}\"", title)
+}
@@ -0,0 +1,205 @@
+use anyhow::{anyhow, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Request, Response, Server as HttpServer};
+
+pub const RELOAD_PATH: &str = "/__kblog/reload";
+
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+pub struct Server {
+    out_dir: PathBuf,
+    http: HttpServer,
+    reloader: Arc<Reloader>,
+}
+
+impl Server {
+    pub fn new<P: AsRef<Path>>(out_dir: P, address: &str) -> Result<Server> {
+        let http = HttpServer::http(address).map_err(|e| anyhow!(e))?;
+        Ok(Server {
+            out_dir: out_dir.as_ref().to_path_buf(),
+            http,
+            reloader: Arc::new(Reloader::new()),
+        })
+    }
+
+    /// Serves the output directory until the process is killed, calling
+    /// `rebuild` and reloading open pages whenever one of `watched` changes.
+    pub fn run<P, F>(&self, watched: &[P], rebuild: F) -> Result<()>
+    where
+        P: AsRef<Path>,
+        F: Fn() -> Result<()> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let _watcher = watch(watched, tx)?;
+        let reloader = Arc::clone(&self.reloader);
+        thread::spawn(move || rebuild_on_change(rx, rebuild, &reloader));
+
+        for request in self.http.incoming_requests() {
+            if request.url() == RELOAD_PATH {
+                let reloader = Arc::clone(&self.reloader);
+                thread::spawn(move || reloader.stream(request));
+            } else if let Err(e) = self.serve_file(request) {
+                // Usually the browser going away mid response, which
+                // shouldn't take the server down with it.
+                eprintln!("failed to respond: {:#}", e);
+            }
+        }
+        Ok(())
+    }
+
+    fn serve_file(&self, request: Request) -> Result<()> {
+        let file = self
+            .file_path(request.url())
+            .and_then(|path| Some((File::open(&path).ok()?, path)));
+        match file {
+            Some((file, path)) => {
+                let response = Response::from_file(file).with_header(content_type(&path));
+                request.respond(response)?;
+            }
+            None => request.respond(Response::from_string("not found").with_status_code(404))?,
+        }
+        Ok(())
+    }
+
+    fn file_path(&self, url: &str) -> Option<PathBuf> {
+        let url_path = url.split(['?', '#']).next().unwrap_or("");
+        let relative = PathBuf::from(percent_decode(url_path.trim_start_matches('/')));
+        let is_safe = relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if !is_safe {
+            return None;
+        }
+
+        let path = self.out_dir.join(relative);
+        if path.is_dir() {
+            Some(path.join("index.html"))
+        } else {
+            Some(path)
+        }
+    }
+}
+
+struct Reloader {
+    clients: Mutex<Vec<Sender<()>>>,
+}
+
+impl Reloader {
+    fn new() -> Reloader {
+        Reloader {
+            clients: Mutex::new(vec![]),
+        }
+    }
+
+    fn reload(&self) {
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain(|client| client.send(()).is_ok());
+    }
+
+    /// Holds a server-sent events connection open, writing a reload event to
+    /// it after every successful rebuild.
+    fn stream(&self, request: Request) {
+        let (tx, rx) = mpsc::channel();
+        self.clients.lock().unwrap().push(tx);
+        let mut writer = request.into_writer();
+        let headers = "HTTP/1.1 200 OK\r\n\
+            Content-Type: text/event-stream\r\n\
+            Cache-Control: no-cache\r\n\
+            Connection: keep-alive\r\n\r\n";
+        if writer.write_all(headers.as_bytes()).is_err() || writer.flush().is_err() {
+            return;
+        }
+        while rx.recv().is_ok() {
+            if writer.write_all(b"data: reload\n\n").is_err() || writer.flush().is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn watch<P: AsRef<Path>>(
+    watched: &[P],
+    tx: Sender<notify::Result<Event>>,
+) -> Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(tx)?;
+    for path in watched.iter().map(AsRef::as_ref).filter(|p| p.exists()) {
+        watcher.watch(path, RecursiveMode::Recursive)?;
+    }
+    Ok(watcher)
+}
+
+fn rebuild_on_change<F>(rx: Receiver<notify::Result<Event>>, rebuild: F, reloader: &Reloader)
+where
+    F: Fn() -> Result<()>,
+{
+    while let Ok(event) = rx.recv() {
+        if !is_change(event) {
+            continue;
+        }
+        // Editors tend to touch a file several times per save, so wait for
+        // things to settle before rebuilding.
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+
+        println!("Change detected, rebuilding");
+        match rebuild() {
+            Ok(()) => reloader.reload(),
+            Err(e) => eprintln!("{:#}", e),
+        }
+    }
+}
+
+fn is_change(event: notify::Result<Event>) -> bool {
+    match event {
+        Ok(event) => !matches!(event.kind, EventKind::Access(_)),
+        Err(_) => false,
+    }
+}
+
+fn content_type(path: &Path) -> Header {
+    let mime = match path.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("xml") => "application/xml",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    };
+    Header::from_bytes("Content-Type", mime).unwrap()
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -1,46 +1,278 @@
+use anyhow::{anyhow, Context, Error as AnyError, Result};
 use askama::Template;
+use chrono::DateTime;
+use minijinja::{
+    escape_formatter, AutoEscape, Environment, Error, ErrorKind, Output, State, Value,
+};
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
 
-use crate::config::{Link, Themes};
-use crate::document::RenderedPost;
+use crate::config::{self, AnalyticsProvider, Link, Themes};
+use crate::document::{self, ArchiveYear, PostLink, Series, Tag};
+use crate::feed;
+use crate::markdown::TocEntry;
 
-#[derive(Template)]
-#[template(path = "post.html")]
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+const PLAUSIBLE_SCRIPT: &str = "https://plausible.io/js/script.js";
+
+/// The HTML templates compiled into kblog. A site can replace any of them by
+/// putting a template with the same name in its own templates directory.
+const BUILT_IN_TEMPLATES: [(&str, &str); 8] = [
+    ("base.html", include_str!("../../templates/base.html")),
+    ("post.html", include_str!("../../templates/post.html")),
+    ("draft.html", include_str!("../../templates/draft.html")),
+    ("index.html", include_str!("../../templates/index.html")),
+    ("tag.html", include_str!("../../templates/tag.html")),
+    ("tags.html", include_str!("../../templates/tags.html")),
+    ("toc.html", include_str!("../../templates/toc.html")),
+    ("archive.html", include_str!("../../templates/archive.html")),
+];
+
+/// A page of the site, rendered with the HTML template named by `TEMPLATE`.
+pub trait Page: Serialize {
+    const TEMPLATE: &'static str;
+}
+
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+impl Templates {
+    /// Loads the built-in templates along with every `.html` file in `dir`,
+    /// which take precedence over the built-in templates of the same name.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Templates> {
+        let mut env = Environment::new();
+        env.set_formatter(format_value);
+        env.add_filter("slugify", |text: &str| document::slugify(text));
+        env.add_filter("date", format_date);
+        for (name, source) in BUILT_IN_TEMPLATES {
+            env.add_template(name, source)?;
+        }
+
+        let dir = dir.as_ref();
+        if dir.exists() {
+            for entry in dir.read_dir()? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|e| e == "html") {
+                    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                    env.add_template_owned(name, fs::read_to_string(&path)?)?;
+                }
+            }
+        }
+        Ok(Templates { env })
+    }
+
+    pub fn render<P: Page>(&self, page: &P) -> Result<String> {
+        Ok(self.env.get_template(P::TEMPLATE)?.render(page)?)
+    }
+}
+
+/// Escapes HTML the same way the old compiled templates did, leaving `/`
+/// alone so URLs stay readable in the generated pages.
+fn format_value(out: &mut Output, state: &State, value: &Value) -> Result<(), Error> {
+    match value.as_str() {
+        Some(text) if state.auto_escape() == AutoEscape::Html && !value.is_safe() => {
+            for c in text.chars() {
+                match c {
+                    '<' => out.write_str("&lt;")?,
+                    '>' => out.write_str("&gt;")?,
+                    '&' => out.write_str("&amp;")?,
+                    '"' => out.write_str("&quot;")?,
+                    '\'' => out.write_str("&#x27;")?,
+                    c => out.write_char(c)?,
+                }
+            }
+            Ok(())
+        }
+        _ => escape_formatter(out, state, value),
+    }
+}
+
+/// Formats an RFC 3339 date, e.g. `{{ post.date|date("%B %-d, %Y") }}`.
+fn format_date(date: &str, format: Option<&str>) -> Result<String, Error> {
+    let parsed = DateTime::parse_from_rfc3339(date)
+        .map_err(|e| Error::new(ErrorKind::InvalidOperation, "not a date").with_source(e))?;
+    Ok(format!(
+        "{}",
+        parsed.format(format.unwrap_or(DEFAULT_DATE_FORMAT))
+    ))
+}
+
+#[derive(Serialize)]
 pub struct PostTemplate<'a> {
     pub title: &'a str,
     pub date: &'a str,
+    pub updated: Option<&'a str>,
+    pub tags: &'a [String],
+    pub toc: Option<&'a [TocEntry]>,
     pub content: &'a str,
+    /// The next oldest post.
+    pub prev: Option<PostLink<'a>>,
+    /// The next newest post.
+    pub next: Option<PostLink<'a>>,
+    pub series: Option<&'a Series<'a>>,
+    /// Where this post falls in its series, starting from 1.
+    pub series_part: Option<usize>,
 }
 
-#[derive(Template)]
-#[template(path = "draft.html")]
+impl Page for PostTemplate<'_> {
+    const TEMPLATE: &'static str = "post.html";
+}
+
+#[derive(Serialize)]
 pub struct DraftTemplate<'a> {
     pub title: &'a str,
+    pub toc: Option<&'a [TocEntry]>,
     pub content: &'a str,
 }
 
-#[derive(Template)]
-#[template(path = "index.html")]
+impl Page for DraftTemplate<'_> {
+    const TEMPLATE: &'static str = "draft.html";
+}
+
+#[derive(Serialize)]
 pub struct IndexTemplate<'a> {
     pub blog_name: &'a str,
     pub blog_subtitle: &'a str,
-    pub posts: &'a [RenderedPost],
+    pub posts: Vec<PostLink<'a>>,
+    pub summaries: bool,
+    pub page: usize,
+    pub page_count: usize,
+    pub prev_url: Option<String>,
+    pub next_url: Option<String>,
 }
 
-#[derive(Template)]
-#[template(path = "base.html")]
+impl Page for IndexTemplate<'_> {
+    const TEMPLATE: &'static str = "index.html";
+}
+
+#[derive(Serialize)]
+pub struct TagTemplate<'a> {
+    pub tag: &'a Tag<'a>,
+    pub feeds: &'a [&'a feed::Format],
+}
+
+impl Page for TagTemplate<'_> {
+    const TEMPLATE: &'static str = "tag.html";
+}
+
+#[derive(Serialize)]
+pub struct TagsTemplate<'a> {
+    pub tags: &'a [Tag<'a>],
+}
+
+impl Page for TagsTemplate<'_> {
+    const TEMPLATE: &'static str = "tags.html";
+}
+
+#[derive(Serialize)]
+pub struct ArchiveTemplate<'a> {
+    /// The year this page is limited to, if any.
+    pub year: Option<i32>,
+    pub years: &'a [ArchiveYear<'a>],
+}
+
+impl Page for ArchiveTemplate<'_> {
+    const TEMPLATE: &'static str = "archive.html";
+}
+
+#[derive(Serialize)]
 pub struct PageTemplate<'a> {
     pub title: &'a str,
     pub og_type: &'a str,
     pub url: &'a str,
     pub blog_name: &'a str,
     pub og_description: &'a str,
-    pub feed_file: &'a str,
+    pub feeds: &'a [&'a feed::Format],
     pub style: &'a str,
     pub body: &'a str,
     pub links: &'a [Link],
     pub year: &'a str,
     pub author: &'a str,
-    pub analytics_tag: &'a str,
+    pub analytics: Option<&'a Analytics>,
+    pub reload_path: Option<&'a str>,
+}
+
+impl Page for PageTemplate<'_> {
+    const TEMPLATE: &'static str = "base.html";
+}
+
+/// The analytics script to include on a page, as given to `base.html`.
+#[derive(Serialize)]
+#[serde(tag = "provider", rename_all = "lowercase")]
+pub enum Analytics {
+    Google { id: String },
+    Plausible { domain: String, script: String },
+    GoatCounter { code: String },
+    Custom { snippet: String },
+}
+
+impl Analytics {
+    /// Checks the analytics config has everything its provider needs,
+    /// returning `None` when analytics are turned off.
+    pub fn from_config(analytics: &config::Analytics, domain: &str) -> Result<Option<Analytics>> {
+        let analytics = match analytics.provider {
+            AnalyticsProvider::None => return Ok(None),
+            AnalyticsProvider::Google => Analytics::Google {
+                id: required(&analytics.id, "id", "google")?,
+            },
+            AnalyticsProvider::Plausible => Analytics::Plausible {
+                domain: analytics.domain.clone().unwrap_or(domain.to_string()),
+                script: analytics
+                    .script
+                    .clone()
+                    .unwrap_or(PLAUSIBLE_SCRIPT.to_string()),
+            },
+            AnalyticsProvider::GoatCounter => Analytics::GoatCounter {
+                code: required(&analytics.code, "code", "goatcounter")?,
+            },
+            AnalyticsProvider::Custom => {
+                let path = analytics
+                    .snippet
+                    .as_ref()
+                    .ok_or_else(|| missing_analytics_setting("snippet", "custom"))?;
+                let snippet = fs::read_to_string(path).with_context(|| {
+                    format!("failed to read analytics snippet {}", path.display())
+                })?;
+                Analytics::Custom { snippet }
+            }
+        };
+        Ok(Some(analytics))
+    }
+}
+
+fn required(value: &Option<String>, setting: &str, provider: &str) -> Result<String> {
+    value
+        .clone()
+        .ok_or_else(|| missing_analytics_setting(setting, provider))
+}
+
+fn missing_analytics_setting(setting: &str, provider: &str) -> AnyError {
+    anyhow!(
+        "analytics provider \"{}\" needs `{}` set in the [analytics] config",
+        provider,
+        setting
+    )
+}
+
+pub struct SitemapUrl {
+    pub loc: String,
+    pub lastmod: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "sitemap.xml")]
+pub struct SitemapTemplate<'a> {
+    pub urls: &'a [SitemapUrl],
+}
+
+#[derive(Template)]
+#[template(path = "robots.txt", escape = "none")]
+pub struct RobotsTemplate<'a> {
+    pub disallow: &'a [String],
+    pub sitemap_url: &'a str,
 }
 
 #[derive(Template)]